@@ -19,8 +19,10 @@ fn main() {
         .whitelist_function("set_clock_source")
         .whitelist_function("set_rx_gain")
         .whitelist_function("get_rx_gain")
+        .whitelist_function("get_rx_num_channels")
         .whitelist_function("set_tx_freq")
         .whitelist_function("set_rx_freq")
+        .whitelist_function("get_rx_lo_locked")
         .whitelist_function("set_time_now")
         .whitelist_function("get_rx_streamer")
         .whitelist_function("get_tx_streamer")
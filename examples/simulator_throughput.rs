@@ -0,0 +1,102 @@
+//! Measures how many samples/sec the simulator can push from a Tx to an Rx, to check that it keeps
+//! up with a real USRP. Run with `cargo run --release --example simulator_throughput`. The Tx and
+//! Rx share a single thread, so the numbers depend on the machine and how busy it is
+
+use num::Complex;
+use std::time::{Duration, Instant};
+use usrp::{
+    create_seeded_simulator, Antennas, ChannelModel, DelayProfile, Fading, MimoChannel, Node,
+    PathLoss, RadioRx, RadioSimulatorConfig, RadioSimulatorConfigBuilder, RadioTx, Trajectory,
+};
+
+/// Sample rate of a USRP N210 streaming 16-bit samples over gigabit ethernet
+const SAMP_RATE: u64 = 25_000_000;
+/// Number of samples sent and received in each call
+const BLOCK_LEN: usize = 100_000;
+/// Time spent measuring each configuration
+const DURATION: Duration = Duration::from_secs(2);
+
+/// Send and receive blocks for `DURATION`. Returns the throughput in samples/sec
+fn measure(config: &RadioSimulatorConfig) -> f64 {
+    let (mut tx, mut rx) = create_seeded_simulator(config, 0);
+    let data: Vec<_> = (0..BLOCK_LEN)
+        .map(|i| Complex::from_polar(&1., &(i as f32 * 0.1)))
+        .collect();
+
+    let start = Instant::now();
+    let mut num_samps = 0;
+    while start.elapsed() < DURATION {
+        tx.send(&data).unwrap();
+        rx.recv(BLOCK_LEN).unwrap();
+        num_samps += BLOCK_LEN;
+    }
+    num_samps as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let builder = || RadioSimulatorConfig::builder().samp_rate(SAMP_RATE);
+    let two_antennas = || Antennas {
+        count: 2,
+        ..Antennas::default()
+    };
+    let configs: Vec<(&str, RadioSimulatorConfigBuilder)> = vec![
+        ("default", builder()),
+        ("fixed CFO", builder().max_cfo(10e3)),
+        (
+            "CFO drift and phase noise",
+            builder().max_cfo(10e3).cfo_drift(1.).phase_noise(1e-3),
+        ),
+        ("SFO", builder().sfo(10.)),
+        (
+            "static multipath",
+            builder().add_multipath(200e-9, Complex::new(0.3, 0.1)),
+        ),
+        (
+            "EPA fading",
+            builder().channel(ChannelModel::Fading {
+                profile: DelayProfile::Epa,
+                fading: Fading::Rayleigh,
+                max_doppler: 5.,
+            }),
+        ),
+        ("8-bit ADC", builder().adc_bits(8)),
+        (
+            "moving Rx",
+            builder().geometry(
+                Node::default(),
+                Node {
+                    position: [100., 0., 0.],
+                    trajectory: Trajectory::ConstantVelocity([30., 0., 0.]),
+                    ..Node::default()
+                },
+                PathLoss::FreeSpace,
+            ),
+        ),
+        (
+            "2x2 fading MIMO",
+            builder()
+                .tx_antennas(two_antennas())
+                .rx_antennas(two_antennas())
+                .mimo_channel(MimoChannel::Rayleigh {
+                    tx_correlation: 0.5,
+                    rx_correlation: 0.5,
+                    max_doppler: 5.,
+                }),
+        ),
+    ];
+
+    println!(
+        "Tx and Rx throughput at {} Msps, in blocks of {} samples",
+        SAMP_RATE as f64 / 1e6,
+        BLOCK_LEN
+    );
+    for (name, config) in configs {
+        let rate = measure(&config.build().unwrap());
+        let verdict = if rate >= SAMP_RATE as f64 {
+            "real time"
+        } else {
+            "slower than real time"
+        };
+        println!("{:>28}: {:6.1} Msps ({})", name, rate / 1e6, verdict);
+    }
+}
@@ -0,0 +1,97 @@
+//! Errors reported by real and simulated radios. The USRP wrapper reports failures as numeric
+//! codes, which are translated to `RadioError` so callers can tell recoverable errors (e.g.
+//! `Overflow`) from fatal ones (e.g. `DeviceNotFound`).
+
+use std::fmt;
+
+/// Why a radio operation failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum RadioError {
+    /// The device could not be found or opened
+    DeviceNotFound,
+    /// Samples were dropped because they weren't read fast enough. Receiving can continue, but
+    /// there will be a gap in the samples
+    Overflow,
+    /// The transmitter wasn't given samples fast enough, so there is a gap in the middle of a
+    /// burst
+    Underflow,
+    /// No samples arrived from the device within the timeout
+    Timeout,
+    /// A timed command or transmission was scheduled for a time that has already passed
+    LateCommand,
+    /// The device expected another stream command that never arrived
+    BrokenChain,
+    /// The channels of a multi-channel stream could not be time-aligned
+    Alignment,
+    /// A malformed packet arrived from the device
+    BadPacket,
+    /// Packets arrived out of sequence, probably because some were dropped
+    OutOfSequence,
+    /// Samples arrived without the timestamp we need to return
+    MissingTimeSpec,
+    /// The caller and the device disagree on the number of channels
+    ChannelMismatch,
+    /// The other end of a simulated radio link has been dropped
+    Disconnected,
+    /// The configuration of a (simulated) radio is invalid. Says what is wrong with it
+    InvalidConfig(String),
+    /// An error code that doesn't correspond to any of the above
+    Unknown(i64),
+}
+
+impl fmt::Display for RadioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RadioError::DeviceNotFound => write!(f, "Radio device not found"),
+            RadioError::Overflow => write!(f, "Overflow: samples were dropped"),
+            RadioError::Underflow => write!(f, "Underflow: ran out of samples to transmit"),
+            RadioError::Timeout => write!(f, "Timed out waiting for the radio"),
+            RadioError::LateCommand => write!(f, "Command arrived after its scheduled time"),
+            RadioError::BrokenChain => write!(f, "Broken chain of stream commands"),
+            RadioError::Alignment => write!(f, "Could not align the channels"),
+            RadioError::BadPacket => write!(f, "Received a bad packet"),
+            RadioError::OutOfSequence => write!(f, "Packets arrived out of sequence"),
+            RadioError::MissingTimeSpec => write!(f, "Samples arrived without a timestamp"),
+            RadioError::ChannelMismatch => write!(f, "Mismatch in the number of channels"),
+            RadioError::Disconnected => write!(f, "The simulated radio link was disconnected"),
+            RadioError::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
+            RadioError::Unknown(code) => write!(f, "Unknown radio error. Got code: {}", code),
+        }
+    }
+}
+
+/// Lets `?` convert a `RadioError` to a `Box<dyn std::error::Error>`
+impl std::error::Error for RadioError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_describes_the_error() {
+        assert_eq!(
+            RadioError::Overflow.to_string(),
+            "Overflow: samples were dropped"
+        );
+        assert_eq!(
+            RadioError::InvalidConfig("samp_rate must be > 0".to_string()).to_string(),
+            "Invalid configuration: samp_rate must be > 0"
+        );
+        assert_eq!(
+            RadioError::Unknown(-42).to_string(),
+            "Unknown radio error. Got code: -42"
+        );
+    }
+
+    #[test]
+    fn converts_to_boxed_error() {
+        fn fails() -> Result<(), Box<dyn std::error::Error>> {
+            Err(RadioError::Timeout)?;
+            Ok(())
+        }
+        assert_eq!(
+            fails().unwrap_err().to_string(),
+            "Timed out waiting for the radio"
+        );
+    }
+}
@@ -1,12 +1,19 @@
+mod error;
 mod simulator;
 #[cfg(feature = "rpi")]
 mod usrp;
 
-pub use simulator::{create_simulator, RadioSimulatorConfig, SimulatedRadioRx, SimulatedRadioTx};
+pub use error::RadioError;
+pub use simulator::{
+    create_seeded_simulator, create_simulator, create_simulator_with_rng, Antennas, ChannelModel,
+    ConfigChange, DelayProfile, Fading, Fault, FaultInjector, GroundTruth, Interferer,
+    IqImpairments, LinkBudget, LinkConfig, LinkTruth, LoSettling, MimoChannel, Mobility, Node,
+    PaModel, PathLoss, RadioSimulatorConfig, RadioSimulatorConfigBuilder, SelfInterference,
+    SimulatedNetwork, SimulatedRadioRx, SimulatedRadioTx, Tap, Trajectory,
+};
 #[cfg(feature = "rpi")]
 pub use usrp::{new_rx_usrp, new_tx_usrp, ClockSource, UsrpRxSingleStream, UsrpTxSingleStream};
 
-use failure::Error;
 use num::complex::Complex;
 
 /// Receive sample from real or simulated radio
@@ -16,16 +23,64 @@ pub trait RadioRx {
     /// first sample. This buffer isn't guaranteed to be constant across multiple calls to `recv`,
     /// since the same piece of memory may be used over and over (and because Rust is awesome, the
     /// type system will automatically capture this error at compile time)
-    fn recv<'a>(&'a mut self, len: usize) -> Result<(&'a [Complex<f32>], u64), Error>;
+    fn recv(&mut self, len: usize) -> Result<(&[Complex<f32>], u64), RadioError>;
     /// Returns count of the number of samples returned since the beginning of the struct
     fn tot_num_samps(&self) -> u64;
     /// Change the center frequency. The oscillator might take some time to settle to the new
-    /// frequency. Ideally, we should check lo_lock before assuming the change is complete, but
+    /// frequency. Ideally, we should check `lo_locked` before assuming the change is complete, but
     /// waiting for a bit could also work
-    fn set_freq(&mut self, freq: f64) -> Result<(), Error>;
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError>;
+    /// Whether the local oscillator has locked to the frequency last set by `set_freq`
+    fn lo_locked(&mut self) -> Result<bool, RadioError>;
+}
+
+/// Receive samples from every channel of a real or simulated radio. `RadioRx::recv` on such a
+/// radio returns only the first channel
+pub trait RadioRxMulti: RadioRx {
+    /// Number of channels in every set of buffers returned by `recv_multi`
+    fn num_channels(&self) -> usize;
+    /// Return one buffer per channel, each containing *exactly* `len` samples, and the timestamp
+    /// (in microseconds) of the first sample. The channels are sampled together, so the i^th
+    /// sample of every buffer was taken at the same instant. As with `RadioRx::recv`, the buffers
+    /// may be reused across calls
+    #[allow(clippy::type_complexity)]
+    fn recv_multi(&mut self, len: usize) -> Result<(&[Vec<Complex<f32>>], u64), RadioError>;
+}
+
+/// Describes how a block of samples passed to `RadioTx::send_burst` should be transmitted
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TxMetadata {
+    /// If `Some`, the first sample is transmitted when the device time (in seconds, as set by
+    /// `set_time_now`) reaches this value. Else it is transmitted as soon as possible
+    pub time: Option<f64>,
+    /// The first sample starts a new burst
+    pub start_of_burst: bool,
+    /// The last sample ends the burst. The radio stops transmitting until the next burst starts
+    pub end_of_burst: bool,
 }
 
 pub trait RadioTx {
-    fn send(&mut self, data: &[Complex<f32>]) -> Result<(), Error>;
-    fn set_freq(&mut self, freq: f64) -> Result<(), Error>;
+    /// Send the samples as part of a continuous stream, as soon as possible
+    fn send(&mut self, data: &[Complex<f32>]) -> Result<(), RadioError> {
+        self.send_burst(data, &TxMetadata::default())
+    }
+    /// Send the samples as described by `md`. This can schedule the transmission for a given
+    /// device time and mark the start and end of bursts
+    fn send_burst(&mut self, data: &[Complex<f32>], md: &TxMetadata) -> Result<(), RadioError>;
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError>;
+}
+
+/// Send samples on every channel of a real or simulated radio. `RadioTx::send_burst` on such a
+/// radio sends on the first channel, and the others are silent
+pub trait RadioTxMulti: RadioTx {
+    /// Number of buffers `send_burst_multi` expects
+    fn num_channels(&self) -> usize;
+    /// Send one buffer per channel, all of the same length, as described by `md`. The i^th sample
+    /// of every buffer is transmitted at the same instant. Returns `RadioError::ChannelMismatch`
+    /// if the buffers don't match the channels
+    fn send_burst_multi(
+        &mut self,
+        data: &[&[Complex<f32>]],
+        md: &TxMetadata,
+    ) -> Result<(), RadioError>;
 }
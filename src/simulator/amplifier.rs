@@ -0,0 +1,218 @@
+//! Nonlinear models of the simulated Tx's power amplifier. Driving the PA harder (with a higher Tx
+//! gain) pushes it into compression, which causes spectral regrowth and distorts high-PAPR signals.
+
+use crate::RadioError;
+use num::{Complex, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Model of a power amplifier. Amplitudes are relative to a unit power signal
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum PaModel {
+    /// An ideal amplifier
+    #[default]
+    Linear,
+    /// Rapp's model of a solid state amplifier, which has no AM/PM distortion. Small signals pass
+    /// unchanged, and the output amplitude smoothly approaches `saturation`. Higher `smoothness`
+    /// makes the transition into saturation sharper (it is typically 2 or 3)
+    Rapp { saturation: f64, smoothness: f64 },
+    /// Saleh's model of a travelling wave tube amplifier. An input of amplitude `r` produces an
+    /// output of amplitude `alpha_a * r / (1 + beta_a * r^2)` with its phase rotated by
+    /// `alpha_phi * r^2 / (1 + beta_phi * r^2)` radians. Saleh's original fit has
+    /// `alpha_a = 2.1587`, `beta_a = 1.1517`, `alpha_phi = 4.0033` and `beta_phi = 9.1040`
+    Saleh {
+        alpha_a: f64,
+        beta_a: f64,
+        alpha_phi: f64,
+        beta_phi: f64,
+    },
+    /// Memory polynomial. The output is the sum over `k` and `m` of
+    /// `coeffs[k][m] * x[n - m] * |x[n - m]|^k`, where `x` is the input. So `coeffs[0]` is a linear
+    /// filter and `coeffs[k][0]` are the memoryless nonlinear terms
+    MemoryPolynomial { coeffs: Vec<Vec<Complex<f32>>> },
+}
+
+impl PaModel {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        match self {
+            PaModel::Linear => {}
+            PaModel::Rapp {
+                saturation,
+                smoothness,
+            } => {
+                if !(saturation.is_finite() && *saturation > 0.) {
+                    return invalid("Rapp saturation must be finite and positive");
+                }
+                if !(smoothness.is_finite() && *smoothness > 0.) {
+                    return invalid("Rapp smoothness must be finite and positive");
+                }
+            }
+            PaModel::Saleh {
+                alpha_a,
+                beta_a,
+                alpha_phi,
+                beta_phi,
+            } => {
+                if [alpha_a, beta_a, alpha_phi, beta_phi]
+                    .iter()
+                    .any(|x| !(x.is_finite() && **x >= 0.))
+                {
+                    return invalid("Saleh parameters must be finite and non-negative");
+                }
+            }
+            PaModel::MemoryPolynomial { coeffs } => {
+                if coeffs.iter().all(|c| c.is_empty()) {
+                    return invalid("the memory polynomial must have at least one coefficient");
+                }
+                if coeffs
+                    .iter()
+                    .flatten()
+                    .any(|c| !(c.re.is_finite() && c.im.is_finite()))
+                {
+                    return invalid("memory polynomial coefficients must be finite");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Applies a `PaModel` to a stream of samples
+pub(super) struct PowerAmplifier {
+    model: PaModel,
+    /// Most recent inputs, latest first. Only used by the memory polynomial
+    history: VecDeque<Complex<f32>>,
+}
+
+impl PowerAmplifier {
+    /// `model` must have been validated
+    pub fn new(model: &PaModel) -> Self {
+        let memory = match model {
+            PaModel::MemoryPolynomial { coeffs } => coeffs.iter().map(Vec::len).max().unwrap(),
+            _ => 1,
+        };
+        Self {
+            model: model.clone(),
+            history: vec![Complex::zero(); memory].into(),
+        }
+    }
+
+    /// Forget past inputs, since the PA was idle
+    pub fn reset(&mut self) {
+        for samp in self.history.iter_mut() {
+            *samp = Complex::zero();
+        }
+    }
+
+    /// Amplify a block of samples, in place
+    pub fn process_block(&mut self, samps: &mut [Complex<f32>]) {
+        if self.model == PaModel::Linear {
+            return;
+        }
+        for samp in samps.iter_mut() {
+            *samp = self.process(*samp);
+        }
+    }
+
+    /// Amplify the next input sample
+    pub fn process(&mut self, samp: Complex<f32>) -> Complex<f32> {
+        let r = samp.norm() as f64;
+        match &self.model {
+            PaModel::Linear => samp,
+            PaModel::Rapp {
+                saturation,
+                smoothness,
+            } => {
+                let p2 = 2. * smoothness;
+                let gain = 1. / (1. + (r / saturation).powf(p2)).powf(1. / p2);
+                samp * gain as f32
+            }
+            PaModel::Saleh {
+                alpha_a,
+                beta_a,
+                alpha_phi,
+                beta_phi,
+            } => {
+                let r2 = r * r;
+                let gain = alpha_a / (1. + beta_a * r2);
+                let phase = alpha_phi * r2 / (1. + beta_phi * r2);
+                samp * Complex::from_polar(&(gain as f32), &(phase as f32))
+            }
+            PaModel::MemoryPolynomial { coeffs } => {
+                self.history.pop_back();
+                self.history.push_front(samp);
+                let mut res = Complex::zero();
+                for (k, coeffs) in coeffs.iter().enumerate() {
+                    for (coeff, x) in coeffs.iter().zip(self.history.iter()) {
+                        res += coeff * x * x.norm().powi(k as i32);
+                    }
+                }
+                res
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples with a range of amplitudes and phases
+    fn inputs() -> Vec<Complex<f32>> {
+        (1..=50)
+            .map(|i| Complex::from_polar(&(i as f32 * 0.1), &(i as f32)))
+            .collect()
+    }
+
+    #[test]
+    fn rapp_saturates() {
+        let model = PaModel::Rapp {
+            saturation: 0.8,
+            smoothness: 3.,
+        };
+        let mut pa = PowerAmplifier::new(&model);
+        // Small signals pass unchanged
+        let small = Complex::new(0.01, -0.02);
+        assert!((pa.process(small) - small).norm() < 1e-6);
+        for samp in inputs() {
+            let out = pa.process(samp);
+            assert!(out.norm() <= 0.8 + 1e-6);
+            // No AM/PM distortion
+            assert!((out.arg() - samp.arg()).abs() < 1e-5);
+        }
+        // Far into compression, the output is at the saturation level
+        let out = pa.process(Complex::new(0., 100.));
+        assert!((out.norm() - 0.8).abs() < 1e-5);
+    }
+
+    #[test]
+    fn saleh_matches_closed_form() {
+        let model = PaModel::Saleh {
+            alpha_a: 2.1587,
+            beta_a: 1.1517,
+            alpha_phi: 4.0033,
+            beta_phi: 9.104,
+        };
+        let mut pa = PowerAmplifier::new(&model);
+        let (r, phase) = (0.5f64, 0.3f64);
+        let out = pa.process(Complex::from_polar(&(r as f32), &(phase as f32)));
+        let amplitude = 2.1587 * r / (1. + 1.1517 * r * r);
+        let rotation = 4.0033 * r * r / (1. + 9.104 * r * r);
+        assert!((out.norm() as f64 - amplitude).abs() < 1e-5);
+        assert!((out.arg() as f64 - (phase + rotation)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn unit_memory_polynomial_is_identity() {
+        let model = PaModel::MemoryPolynomial {
+            coeffs: vec![vec![Complex::new(1., 0.)]],
+        };
+        let mut pa = PowerAmplifier::new(&model);
+        let mut samps = inputs();
+        pa.process_block(&mut samps);
+        assert_eq!(samps, inputs());
+    }
+}
@@ -0,0 +1,626 @@
+//! Models of the multipath channel between the Tx and the Rx. The channel is a tapped delay line
+//! whose taps are either fixed (`ChannelModel::Static`) or fade over time following a Jakes
+//! Doppler spectrum (`ChannelModel::Fading`). Taps may have fractional delays, which are
+//! implemented with a windowed sinc interpolator.
+
+use num::{Complex, Zero};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Number of sinusoids summed to generate each fading process. More sinusoids approximate the
+/// Jakes spectrum better, but take longer to compute
+const NUM_SINUSOIDS: usize = 16;
+/// Fractional delays are implemented by a windowed sinc filter that extends this many samples on
+/// either side of the delay
+const SINC_HALF_WIDTH: f64 = 4.;
+/// Fading gains are held constant for at most this many samples, so that the channel's filter
+/// doesn't have to be recomputed for every sample
+const MAX_FADING_UPDATE_INTERVAL: usize = 256;
+/// The gains are also updated at least once every this fraction of a cycle of the maximum Doppler
+/// shift, so that holding them constant doesn't distort the fading
+const FADING_UPDATE_CYCLES: f64 = 1e-3;
+
+/// A tap of a power delay profile
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tap {
+    /// Delay in secs
+    pub delay: f64,
+    /// Average power in dB
+    pub power: f64,
+}
+
+/// Power delay profiles of standard channel models
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum DelayProfile {
+    /// 3GPP Extended Pedestrian A (TS 36.101, annex B.2.1)
+    Epa,
+    /// 3GPP Extended Vehicular A (TS 36.101, annex B.2.1)
+    Eva,
+    /// 3GPP Extended Typical Urban (TS 36.101, annex B.2.1)
+    Etu,
+    /// IEEE 802.11n (TGn) model B: residential, 15 ns RMS delay spread. The two clusters of the
+    /// model are added up into a single tapped delay line
+    TgnB,
+    /// Any other profile
+    Custom(Vec<Tap>),
+}
+
+/// Statistics of the fading taps
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum Fading {
+    /// No line of sight. Every tap is a zero-mean complex gaussian process
+    Rayleigh,
+    /// The first tap has a line of sight component whose power is `k_factor` dB more than the
+    /// power of its scattered component. The other taps are Rayleigh
+    Rician { k_factor: f64 },
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum ChannelModel {
+    /// A direct path with unit gain plus fixed multipath components given as (delay in secs,
+    /// complex gain). Each component also gets the phase shift a carrier at the current frequency
+    /// accumulates over its delay
+    Static(Vec<(f64, Complex<f32>)>),
+    /// Every tap of `profile` fades independently with a Jakes (classical) Doppler spectrum whose
+    /// maximum Doppler shift is `max_doppler` Hz. The average power of the channel is 1
+    Fading {
+        profile: DelayProfile,
+        fading: Fading,
+        max_doppler: f64,
+    },
+}
+
+impl DelayProfile {
+    /// The taps of the profile, normalized so that their powers add up to 1 (0 dB)
+    pub fn taps(&self) -> Vec<Tap> {
+        // (delay in ns, power in dB)
+        let table: Vec<(f64, f64)> = match self {
+            DelayProfile::Epa => vec![
+                (0., 0.),
+                (30., -1.),
+                (70., -2.),
+                (90., -3.),
+                (110., -8.),
+                (190., -17.2),
+                (410., -20.8),
+            ],
+            DelayProfile::Eva => vec![
+                (0., 0.),
+                (30., -1.5),
+                (150., -1.4),
+                (310., -3.6),
+                (370., -0.6),
+                (710., -9.1),
+                (1090., -7.),
+                (1730., -12.),
+                (2510., -16.9),
+            ],
+            DelayProfile::Etu => vec![
+                (0., -1.),
+                (50., -1.),
+                (120., -1.),
+                (200., 0.),
+                (230., 0.),
+                (500., 0.),
+                (1600., -3.),
+                (2300., -5.),
+                (5000., -7.),
+            ],
+            DelayProfile::TgnB => {
+                let cluster1 = [
+                    (0., 0.),
+                    (10., -5.4),
+                    (20., -10.8),
+                    (30., -16.2),
+                    (40., -21.7),
+                ];
+                let cluster2 = [
+                    (20., -3.2),
+                    (30., -6.3),
+                    (40., -9.4),
+                    (50., -12.5),
+                    (60., -15.6),
+                    (70., -18.7),
+                    (80., -21.8),
+                ];
+                // Add the powers of the clusters at each delay
+                (0..9)
+                    .map(|i| {
+                        let delay = 10. * i as f64;
+                        let power: f64 = cluster1
+                            .iter()
+                            .chain(cluster2.iter())
+                            .filter(|(d, _)| *d == delay)
+                            .map(|(_, p)| 10f64.powf(p / 10.))
+                            .sum();
+                        (delay, 10. * power.log10())
+                    })
+                    .collect()
+            }
+            DelayProfile::Custom(taps) => taps.iter().map(|t| (t.delay * 1e9, t.power)).collect(),
+        };
+
+        let tot_power: f64 = table.iter().map(|(_, p)| 10f64.powf(p / 10.)).sum();
+        table
+            .iter()
+            .map(|(d, p)| Tap {
+                delay: d * 1e-9,
+                power: p - 10. * tot_power.log10(),
+            })
+            .collect()
+    }
+}
+
+/// A sinusoid of the sum-of-sinusoids fading model, represented as a rotating phasor
+struct Sinusoid {
+    phasor: Complex<f64>,
+    /// Multiplied into `phasor` at every update of the fading gains
+    rotation: Complex<f64>,
+}
+
+impl Sinusoid {
+    /// A sinusoid with a random phase and frequency `doppler` (in cycles/sample), which advances
+    /// `interval` samples at a time
+    fn new<R: Rng>(doppler: f64, interval: usize, rng: &mut R) -> Self {
+        Self {
+            phasor: Complex::from_polar(&1., &(2. * PI * rng.gen::<f64>())),
+            rotation: Complex::from_polar(&1., &(2. * PI * doppler * interval as f64)),
+        }
+    }
+
+    fn advance(&mut self) {
+        self.phasor *= self.rotation;
+        // Rotating phasors accumulate rounding errors
+        self.phasor /= self.phasor.norm_sqr().sqrt();
+    }
+}
+
+/// A zero-mean complex gaussian process with unit power and a Jakes Doppler spectrum, i.e. the
+/// scattered component of a fading gain, approximated by a sum of sinusoids
+pub(super) struct Scatter {
+    sinusoids: Vec<Sinusoid>,
+}
+
+impl Scatter {
+    /// A process whose maximum Doppler shift is `doppler` (in cycles/sample), which advances
+    /// `interval` samples at a time
+    pub fn new<R: Rng>(doppler: f64, interval: usize, rng: &mut R) -> Self {
+        // Angles of arrival are spread evenly with a random offset, which approximates the Jakes
+        // spectrum well with few sinusoids
+        let offset = rng.gen::<f64>();
+        let sinusoids = (0..NUM_SINUSOIDS)
+            .map(|n| {
+                let angle = 2. * PI * (n as f64 + offset) / NUM_SINUSOIDS as f64;
+                Sinusoid::new(doppler * angle.cos(), interval, rng)
+            })
+            .collect();
+        Self { sinusoids }
+    }
+
+    /// The current value of the process
+    pub fn gain(&self) -> Complex<f64> {
+        let sum: Complex<f64> = self.sinusoids.iter().map(|s| s.phasor).sum();
+        sum / (self.sinusoids.len() as f64).sqrt()
+    }
+
+    /// Move on to the next update
+    pub fn advance(&mut self) {
+        for s in &mut self.sinusoids {
+            s.advance();
+        }
+    }
+}
+
+/// Number of samples for which fading gains whose maximum Doppler shift is `doppler` (in
+/// cycles/sample) are held constant
+pub(super) fn fading_update_interval(doppler: f64) -> usize {
+    (FADING_UPDATE_CYCLES / doppler)
+        .floor()
+        .clamp(1., MAX_FADING_UPDATE_INTERVAL as f64) as usize
+}
+
+/// A single tap of the tapped delay line
+struct ChannelTap {
+    /// Delay in secs
+    delay: f64,
+    /// Index into the past samples (0 is the latest) of the first coefficient of `kernel`
+    offset: usize,
+    /// Interpolation filter implementing the (possibly fractional) delay
+    kernel: Vec<f32>,
+    /// For static taps, the gain excluding the carrier phase shift. For fading taps, the RMS
+    /// amplitude of the scattered component
+    gain: Complex<f32>,
+    /// Scattered component of a fading tap. `None` for static taps
+    scatter: Option<Scatter>,
+    /// Line of sight component of a Rician tap and its amplitude
+    los: Option<(Sinusoid, f64)>,
+    /// The gain currently applied to the tap
+    cur_gain: Complex<f32>,
+}
+
+impl ChannelTap {
+    fn new(delay: f64, samp_rate: f64, gain: Complex<f32>) -> Self {
+        let (offset, kernel) = fractional_delay_kernel(delay * samp_rate);
+        Self {
+            delay,
+            offset,
+            kernel,
+            gain,
+            scatter: None,
+            los: None,
+            cur_gain: gain,
+        }
+    }
+
+    /// Recompute `cur_gain` for fading taps
+    fn update_fading_gain(&mut self) {
+        let scatter = match &self.scatter {
+            Some(scatter) => scatter,
+            None => return,
+        };
+        let mut gain = scatter.gain() * self.gain.re as f64;
+        if let Some((los, amp)) = &self.los {
+            gain += los.phasor * amp;
+        }
+        self.cur_gain = Complex::new(gain.re as f32, gain.im as f32);
+    }
+}
+
+/// Filter that delays a signal by `delay` samples. Returns the delay of the first coefficient
+/// (in samples) and the coefficients. Since the filter has to be causal, it is truncated at 0,
+/// which makes it less accurate for delays less than `SINC_HALF_WIDTH` samples
+fn fractional_delay_kernel(delay: f64) -> (usize, Vec<f32>) {
+    let nearest = delay.round();
+    if (delay - nearest).abs() < 1e-6 {
+        return (nearest as usize, vec![1.]);
+    }
+
+    let first = (delay - SINC_HALF_WIDTH).ceil().max(0.) as usize;
+    let last = (delay + SINC_HALF_WIDTH).floor() as usize;
+    let mut kernel: Vec<f32> = (first..=last)
+        .map(|k| {
+            let x = k as f64 - delay;
+            let sinc = (PI * x).sin() / (PI * x);
+            // Hann window
+            let window = 0.5 * (1. + (PI * x / SINC_HALF_WIDTH).cos());
+            (sinc * window) as f32
+        })
+        .collect();
+    // Unit gain at DC, even if the filter was truncated
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|c| *c /= sum);
+    (first, kernel)
+}
+
+/// The tapped delay line the Tx's signal passes through before reaching the Rx. Blocks of samples
+/// are filtered with the sum of the taps' interpolation filters, scaled by their current gains
+pub(super) struct MultipathChannel {
+    taps: Vec<ChannelTap>,
+    /// Impulse response of the channel, in reverse order: the last coefficient applies to the
+    /// latest sample
+    filter: Vec<Complex<f32>>,
+    /// The last `filter.len() - 1` input samples, oldest first, followed by the block being
+    /// processed
+    history: Vec<Complex<f32>>,
+    /// Number of samples between updates of the fading gains. `None` for static channels
+    update_interval: Option<usize>,
+    /// Samples left before the next update of the fading gains
+    until_update: usize,
+}
+
+impl MultipathChannel {
+    pub fn new<R: Rng>(model: &ChannelModel, samp_rate: f64, freq: f64, rng: &mut R) -> Self {
+        let mut update_interval = None;
+        let taps: Vec<ChannelTap> = match model {
+            ChannelModel::Static(multipath) => std::iter::once((0., Complex::new(1., 0.)))
+                .chain(multipath.iter().cloned())
+                .map(|(delay, gain)| ChannelTap::new(delay, samp_rate, gain))
+                .collect(),
+            ChannelModel::Fading {
+                profile,
+                fading,
+                max_doppler,
+            } => {
+                // Maximum doppler in cycles/sample
+                let doppler = max_doppler / samp_rate;
+                let interval = fading_update_interval(doppler);
+                update_interval = Some(interval);
+                profile
+                    .taps()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, t)| {
+                        let power = 10f64.powf(t.power / 10.);
+                        // Fraction of the power in the line of sight component
+                        let los_frac = match fading {
+                            Fading::Rician { k_factor } if i == 0 => {
+                                let k = 10f64.powf(k_factor / 10.);
+                                k / (k + 1.)
+                            }
+                            _ => 0.,
+                        };
+                        let scatter_amp = (power * (1. - los_frac)).sqrt() as f32;
+                        let mut tap = ChannelTap::new(t.delay, samp_rate, scatter_amp.into());
+                        tap.scatter = Some(Scatter::new(doppler, interval, rng));
+                        if los_frac > 0. {
+                            let angle = 2. * PI * rng.gen::<f64>();
+                            let los = Sinusoid::new(doppler * angle.cos(), interval, rng);
+                            tap.los = Some((los, (power * los_frac).sqrt()));
+                        }
+                        tap.update_fading_gain();
+                        tap
+                    })
+                    .collect()
+            }
+        };
+
+        Self::with_taps(taps, update_interval, freq)
+    }
+
+    /// A channel with just the given static taps, as (delay in secs, complex gain). Unlike
+    /// `ChannelModel::Static`, there is no direct path unless it is one of the taps
+    pub fn from_taps(taps: &[(f64, Complex<f32>)], samp_rate: f64, freq: f64) -> Self {
+        let taps = taps
+            .iter()
+            .map(|(delay, gain)| ChannelTap::new(*delay, samp_rate, *gain))
+            .collect();
+        Self::with_taps(taps, None, freq)
+    }
+
+    fn with_taps(taps: Vec<ChannelTap>, update_interval: Option<usize>, freq: f64) -> Self {
+        let filter_len = taps
+            .iter()
+            .map(|t| t.offset + t.kernel.len())
+            .max()
+            .unwrap_or(1);
+        let mut res = Self {
+            taps,
+            filter: vec![Complex::zero(); filter_len],
+            // Silent before the first sample
+            history: vec![Complex::zero(); filter_len - 1],
+            update_interval,
+            until_update: update_interval.unwrap_or(0),
+        };
+        res.set_freq(freq);
+        res
+    }
+
+    /// Static taps are phase shifted by the distance the carrier travelled, which depends on the
+    /// frequency
+    pub fn set_freq(&mut self, freq: f64) {
+        for tap in &mut self.taps {
+            if tap.scatter.is_none() {
+                let phase = -2. * PI * freq * tap.delay;
+                tap.cur_gain = tap.gain * Complex::from_polar(&1., &(phase as f32));
+            }
+        }
+        self.update_filter();
+    }
+
+    /// The delay (in secs) and current gain of every tap
+    pub fn taps(&self) -> Vec<(f64, Complex<f32>)> {
+        self.taps
+            .iter()
+            .map(|tap| (tap.delay, tap.cur_gain))
+            .collect()
+    }
+
+    /// Pass a block of samples through the channel, in place
+    pub fn process_block(&mut self, samps: &mut [Complex<f32>]) {
+        // A single path whose gain never changes, e.g. the default channel, is just a scaling
+        if self.filter.len() == 1 && self.update_interval.is_none() {
+            let gain = self.filter[0];
+            if gain != Complex::new(1., 0.) {
+                samps.iter_mut().for_each(|samp| *samp *= gain);
+            }
+            return;
+        }
+
+        self.history.extend_from_slice(samps);
+        let mut pos = 0;
+        while pos < samps.len() {
+            // Fading gains are constant till the next update
+            let len = match self.update_interval {
+                Some(interval) => {
+                    if self.until_update == 0 {
+                        self.advance_fading();
+                        self.until_update = interval;
+                    }
+                    self.until_update.min(samps.len() - pos)
+                }
+                None => samps.len(),
+            };
+            // Add up the contributions of each coefficient in turn, which vectorizes well
+            let out = &mut samps[pos..pos + len];
+            out.iter_mut().for_each(|samp| *samp = Complex::zero());
+            for (k, c) in self.filter.iter().enumerate() {
+                if c.is_zero() {
+                    continue;
+                }
+                for (samp, x) in out.iter_mut().zip(&self.history[pos + k..pos + k + len]) {
+                    *samp += c * x;
+                }
+            }
+            self.until_update = self.until_update.saturating_sub(len);
+            pos += len;
+        }
+        // Keep the samples the next block needs
+        self.history.drain(..samps.len());
+    }
+
+    /// Advance the fading processes to the next update
+    fn advance_fading(&mut self) {
+        for tap in &mut self.taps {
+            if let Some(scatter) = &mut tap.scatter {
+                scatter.advance();
+            }
+            if let Some((los, _)) = &mut tap.los {
+                los.advance();
+            }
+            tap.update_fading_gain();
+        }
+        self.update_filter();
+    }
+
+    /// Recompute `filter` from the taps' current gains
+    fn update_filter(&mut self) {
+        let last = self.filter.len() - 1;
+        self.filter.iter_mut().for_each(|c| *c = Complex::zero());
+        for tap in &self.taps {
+            for (i, c) in tap.kernel.iter().enumerate() {
+                self.filter[last - tap.offset - i] += tap.cur_gain * c;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SAMP_RATE: f64 = 1e6;
+
+    /// A complex exponential at `freq` cycles/sample
+    fn tone(freq: f64, len: usize) -> Vec<Complex<f32>> {
+        (0..len)
+            .map(|n| Complex::from_polar(&1., &(2. * PI * freq * n as f64)))
+            .map(|samp| Complex::new(samp.re as f32, samp.im as f32))
+            .collect()
+    }
+
+    #[test]
+    fn single_static_tap_is_delay_and_gain() {
+        let gain = Complex::new(0.5, -0.25);
+        let mut channel = MultipathChannel::from_taps(&[(3. / SAMP_RATE, gain)], SAMP_RATE, 0.);
+        let input = tone(0.01, 100);
+        let mut output = input.clone();
+        // Across blocks, the channel remembers the samples it needs
+        let (first, second) = output.split_at_mut(40);
+        channel.process_block(first);
+        channel.process_block(second);
+        assert!(output[..3].iter().all(|samp| samp.is_zero()));
+        for (out, x) in output[3..].iter().zip(&input) {
+            assert!((out - x * gain).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn fractional_delay_is_placed_correctly() {
+        let delay = 10.5;
+        let mut channel = MultipathChannel::from_taps(
+            &[(delay / SAMP_RATE, Complex::new(1., 0.))],
+            SAMP_RATE,
+            0.,
+        );
+        assert_eq!(channel.taps()[0].0, delay / SAMP_RATE);
+
+        // An impulse comes out spread around the delay, with its peak halfway between the
+        // neighbouring samples
+        let mut impulse = vec![Complex::zero(); 32];
+        impulse[0] = Complex::new(1., 0.);
+        channel.process_block(&mut impulse);
+        let (peak, _) = impulse
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.norm().partial_cmp(&b.norm()).unwrap())
+            .unwrap();
+        assert!(peak == 10 || peak == 11);
+        assert!((impulse[10] - impulse[11]).norm() < 1e-6);
+        let sum: Complex<f32> = impulse.iter().sum();
+        assert!((sum - Complex::new(1., 0.)).norm() < 1e-5);
+
+        // A slow tone is just shifted in phase by the delay
+        let freq = 0.01;
+        let mut channel = MultipathChannel::from_taps(
+            &[(delay / SAMP_RATE, Complex::new(1., 0.))],
+            SAMP_RATE,
+            0.,
+        );
+        let mut output = tone(freq, 200);
+        channel.process_block(&mut output);
+        let expected = tone(freq, 200);
+        let shift = tone(-freq * delay, 2)[1];
+        for (out, x) in output[20..].iter().zip(&expected[20..]) {
+            assert!((out - x * shift).norm() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn fading_channel_has_unit_average_power() {
+        let model = ChannelModel::Fading {
+            profile: DelayProfile::Epa,
+            fading: Fading::Rayleigh,
+            max_doppler: 1e4,
+        };
+        // A constant input passes through the sum of the taps' gains, whose powers add up to 1.
+        // Average over many channels, each over many cycles of the Doppler shift
+        let (num_channels, len) = (20, 5_000);
+        let mut power = 0.;
+        for seed in 0..num_channels {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut channel = MultipathChannel::new(&model, SAMP_RATE, 2.4e9, &mut rng);
+            let mut samps = vec![Complex::new(1., 0.); len];
+            channel.process_block(&mut samps);
+            power += samps.iter().map(|s| s.norm_sqr() as f64).sum::<f64>();
+        }
+        power /= (num_channels as usize * len) as f64;
+        assert!((power - 1.).abs() < 0.1, "power {}", power);
+    }
+
+    /// Bessel function of the first kind of order 0, from its power series, which converges
+    /// quickly for small `x`
+    fn bessel_j0(x: f64) -> f64 {
+        let mut term = 1.;
+        let mut sum = 1.;
+        for k in 1..30 {
+            term *= -(x / 2.).powi(2) / (k * k) as f64;
+            sum += term;
+        }
+        sum
+    }
+
+    #[test]
+    fn fading_follows_the_jakes_autocorrelation() {
+        // The autocorrelation of a tap with a Jakes spectrum is J0(2 pi max_doppler lag)
+        let max_doppler = 100.;
+        let model = ChannelModel::Fading {
+            profile: DelayProfile::Custom(vec![Tap {
+                delay: 0.,
+                power: 0.,
+            }]),
+            fading: Fading::Rayleigh,
+            max_doppler,
+        };
+        let lags = [0, 500, 1000, 2000, 3828, 6000];
+        let (num_channels, len) = (100, 10_000);
+        let mut corr = vec![Complex::<f64>::zero(); lags.len()];
+        for seed in 0..num_channels {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut channel = MultipathChannel::new(&model, SAMP_RATE, 2.4e9, &mut rng);
+            let mut gains = vec![Complex::new(1., 0.); len + lags[lags.len() - 1]];
+            channel.process_block(&mut gains);
+            for (corr, lag) in corr.iter_mut().zip(&lags) {
+                for (a, b) in gains.iter().zip(&gains[*lag..len + lag]) {
+                    let prod = a * b.conj();
+                    *corr += Complex::new(prod.re as f64, prod.im as f64);
+                }
+            }
+        }
+        for (corr, lag) in corr.iter().zip(&lags) {
+            let corr = corr.re / (num_channels as usize * len) as f64;
+            let expected = bessel_j0(2. * PI * max_doppler * *lag as f64 / SAMP_RATE);
+            assert!(
+                (corr - expected).abs() < 0.1,
+                "lag {}: {} instead of {}",
+                lag,
+                corr,
+                expected
+            );
+        }
+    }
+}
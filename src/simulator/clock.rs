@@ -0,0 +1,109 @@
+//! Time in a simulation. `Medium` keeps the time (in samples) shared by all the radios of a
+//! simulation, which either advances as fast as the receivers consume samples or, in real time
+//! mode, with the wall clock. The device clock of a simulated radio advances with the medium's
+//! time at the sample rate, and can be set with `RadioRx::set_time_now` just like a USRP's.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The current time in the medium, in samples
+#[derive(Debug)]
+pub(super) struct Medium {
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// The furthest any Rx has received up to
+    received: AtomicU64,
+    /// In real time mode, when the medium's first sample was on the air and how many samples the
+    /// radios can buffer
+    pacing: Option<(Instant, u64)>,
+}
+
+impl Medium {
+    /// If `buffer_secs` is `Some`, time follows the wall clock, starting now, and radios can buffer
+    /// that many seconds of samples. Else it advances as the receivers receive samples
+    pub fn new(samp_rate: u64, buffer_secs: Option<f64>) -> Self {
+        let samp_rate = samp_rate as f64;
+        Self {
+            samp_rate,
+            received: AtomicU64::new(0),
+            pacing: buffer_secs.map(|secs| (Instant::now(), (secs * samp_rate).ceil() as u64)),
+        }
+    }
+
+    /// The current time. Untimed transmissions start from here
+    pub fn now(&self) -> u64 {
+        match self.pacing {
+            Some((start, _)) => (start.elapsed().as_secs_f64() * self.samp_rate) as u64,
+            None => self.received.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Sample rate (in samples/sec) of the radios
+    pub fn samp_rate(&self) -> u64 {
+        self.samp_rate as u64
+    }
+
+    /// Number of samples the radios can buffer, or `None` if time doesn't follow the wall clock
+    pub fn buffer_len(&self) -> Option<u64> {
+        self.pacing.map(|(_, len)| len)
+    }
+
+    /// Note that an Rx has received everything before `index`
+    pub fn received(&self, index: u64) {
+        self.received.fetch_max(index, Ordering::SeqCst);
+    }
+
+    /// In real time mode, wait till the medium reaches `index`. Else return immediately
+    pub fn wait_until(&self, index: u64) {
+        if let Some((start, _)) = self.pacing {
+            let target = start + Duration::from_secs_f64(index as f64 / self.samp_rate);
+            let now = Instant::now();
+            if target > now {
+                std::thread::sleep(target - now);
+            }
+        }
+    }
+}
+
+/// Converts between sample indices in the medium's timeline and device time (in secs). Clones
+/// share the same clock, like the Tx and Rx of one device
+#[derive(Clone, Debug)]
+pub(super) struct VirtualClock {
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// Device time (in secs) at sample 0 of the medium
+    offset: Arc<Mutex<f64>>,
+}
+
+impl VirtualClock {
+    /// A clock that reads 0 at the medium's first sample
+    pub fn new(samp_rate: u64) -> Self {
+        Self {
+            samp_rate: samp_rate as f64,
+            offset: Arc::new(Mutex::new(0.)),
+        }
+    }
+
+    /// Set the device time to `now` (in secs) at the medium's sample `index`
+    pub fn set_time(&self, index: u64, now: f64) {
+        *self.offset.lock().unwrap() = now - index as f64 / self.samp_rate;
+    }
+
+    /// Device time (in secs) at the medium's sample `index`
+    pub fn time(&self, index: u64) -> f64 {
+        *self.offset.lock().unwrap() + index as f64 / self.samp_rate
+    }
+
+    /// Device time (in microseconds) at the medium's sample `index`, as returned by
+    /// `RadioRx::recv`. Times before 0 are reported as 0
+    pub fn time_us(&self, index: u64) -> u64 {
+        (self.time(index) * 1e6).round() as u64
+    }
+
+    /// Index of the medium's sample closest to device time `time` (in secs). Negative if that is
+    /// before the medium started
+    pub fn index(&self, time: f64) -> f64 {
+        ((time - *self.offset.lock().unwrap()) * self.samp_rate).round()
+    }
+}
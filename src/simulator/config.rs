@@ -0,0 +1,820 @@
+//! Parameters of the simulated channel between `SimulatedRadioTx` and `SimulatedRadioRx`. A
+//! `RadioSimulatorConfig` can only be created through `RadioSimulatorConfigBuilder`, which checks
+//! that the parameters make sense, or loaded from a scenario file, which is checked the same way.
+//! `LinkConfig` describes a single link of a `SimulatedNetwork` and is checked when it is added to
+//! the network.
+
+use super::amplifier::PaModel;
+use super::channel::{ChannelModel, Fading};
+use super::duplex::SelfInterference;
+use super::geometry::{LinkBudget, Mobility, Node, PathLoss};
+use super::impairments::IqImpairments;
+use super::interference::Interferer;
+use super::mimo::{Antennas, MimoChannel};
+use super::oscillator::LoSettling;
+use super::scenario::{noise_power, validate_timeline, ConfigChange};
+use crate::RadioError;
+use num::Complex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Parameters of the link from a simulated Tx to a simulated Rx
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkConfig {
+    /// Gain (in dB) of the link. Path loss is a negative gain
+    pub gain: f64,
+    /// Number of samples it takes for the Tx's signal to reach the Rx
+    pub delay: u64,
+    /// The link starts with a random CFO in [-max_cfo, max_cfo] Hz. In addition, the CFO may
+    /// drift as a random walk
+    pub max_cfo: f64,
+    /// In addition to the random starting point, the CFO will exhibit a bounded (by `max_cfo`)
+    /// random walk whose steps have the given standard deviation (in Hz per sample). This models
+    /// cfo drift. For speed, the simulator combines the steps of a few dozen samples into one
+    pub cfo_drift: f64,
+    /// In addition to the cfo, the phase shift per sample will have a random component that is
+    /// normally distributed with a standard deviation of phase_noise radians per sample
+    pub phase_noise: f64,
+    /// Offset (in ppm) of the Rx's sample clock relative to the Tx's. If positive, the Rx samples
+    /// faster and each burst from the Tx appears stretched. As a simplification, the timing error
+    /// built up during a burst is forgotten whenever the Tx falls silent, so every burst starts at
+    /// the sample it was sent for rather than drifting ever further from it
+    pub sfo: f64,
+    /// Rate (in ppm/sec) at which `sfo` changes
+    pub sfo_drift: f64,
+    /// If true, the Tx and Rx each derive their carrier and sample clock from one oscillator, so
+    /// the carrier is offset by `sfo` ppm as well. This CFO is in addition to the random CFO
+    pub cfo_from_sfo: bool,
+    /// The multipath channel of the link
+    pub channel: ChannelModel,
+    /// If `Some`, the nodes move, and the link's gain, delay and Doppler shift follow their
+    /// geometry over time, in addition to `gain` and `delay` (which are then usually 0)
+    pub mobility: Option<Mobility>,
+    /// Gains from the Tx's antennas to the Rx's, if either has more than one
+    pub mimo: MimoChannel,
+}
+
+impl Default for LinkConfig {
+    /// A link with unit gain and no delay, CFO, phase noise, sample clock offset or multipath
+    fn default() -> Self {
+        Self {
+            gain: 0.,
+            delay: 0,
+            max_cfo: 0.,
+            cfo_drift: 0.,
+            phase_noise: 0.,
+            sfo: 0.,
+            sfo_drift: 0.,
+            cfo_from_sfo: false,
+            channel: ChannelModel::Static(Vec::new()),
+            mobility: None,
+            mimo: MimoChannel::Identity,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Check that the parameters make sense for radios with the given sample rate
+    pub(super) fn validate(&self, samp_rate: u64) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if !self.gain.is_finite() {
+            return invalid("link gain must be finite");
+        }
+        if !(self.max_cfo >= 0. && self.max_cfo < samp_rate as f64 / 2.) {
+            return invalid("max_cfo must be non-negative and less than samp_rate / 2");
+        }
+        if !(self.cfo_drift.is_finite() && self.cfo_drift >= 0.) {
+            return invalid("cfo_drift must be finite and non-negative");
+        }
+        if !(self.phase_noise.is_finite() && self.phase_noise >= 0.) {
+            return invalid("phase_noise must be finite and non-negative");
+        }
+        // Real oscillators are off by tens of ppm at most
+        if !(self.sfo.is_finite() && self.sfo.abs() < 1e4) {
+            return invalid("sfo must be less than 1e4 ppm in magnitude");
+        }
+        if !self.sfo_drift.is_finite() {
+            return invalid("sfo_drift must be finite");
+        }
+        // (delay, whether the gain is finite) of every tap of the channel
+        let taps: Vec<(f64, bool)> = match &self.channel {
+            ChannelModel::Static(multipath) => multipath
+                .iter()
+                .map(|(d, g)| (*d, g.re.is_finite() && g.im.is_finite()))
+                .collect(),
+            ChannelModel::Fading {
+                profile,
+                fading,
+                max_doppler,
+            } => {
+                if !(*max_doppler >= 0. && *max_doppler < samp_rate as f64 / 2.) {
+                    return invalid("max_doppler must be non-negative and less than samp_rate / 2");
+                }
+                if let Fading::Rician { k_factor } = fading {
+                    if !k_factor.is_finite() {
+                        return invalid("Rician k_factor must be finite");
+                    }
+                }
+                let taps = profile.taps();
+                if taps.is_empty() {
+                    return invalid("the delay profile must have at least one tap");
+                }
+                taps.iter()
+                    .map(|t| (t.delay, t.power.is_finite()))
+                    .collect()
+            }
+        };
+        for (delay, finite_gain) in taps {
+            if !(delay.is_finite() && delay >= 0.) {
+                return invalid("multipath delays must be finite and non-negative");
+            }
+            // Keep it sane! We store this many past samples
+            if delay * samp_rate as f64 >= 1e6 {
+                return invalid("multipath delays must be less than 1e6 samples");
+            }
+            if !finite_gain {
+                return invalid("multipath gains must be finite");
+            }
+        }
+        if let Some(mobility) = &self.mobility {
+            mobility.validate()?;
+        }
+        self.mimo.validate(samp_rate)?;
+        Ok(())
+    }
+}
+
+// `remote = "Self"` derives the (de)serialization as inherent functions, which the trait impls
+// below wrap, so that deserialized configs are checked like built ones
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(
+    remote = "Self",
+    default = "RadioSimulatorConfig::defaults",
+    deny_unknown_fields
+)]
+pub struct RadioSimulatorConfig {
+    /// To simulate the fact that the Tx and Rx start producing samples at different times, the Rx
+    /// will produce N pure noise values before including signal from the Tx. Here, N is sampled
+    /// uniformly from [0, max_start_time_offset). Units are in samples
+    max_start_time_offset: u64,
+    /// Sample rate (in samples/sec)
+    samp_rate: u64,
+    /// Frequency of operation (in Hz) at which we start. Can be editied *ONLY* using
+    /// `RadioRx::set_freq`
+    start_freq: f64,
+    /// Power (in dB) of the gaussian noise that will be added to the signal, relative to a signal
+    /// with unit power. Hence the SNR of a unit power signal is `-noise_power`
+    #[serde(with = "noise_power")]
+    noise_power: f64,
+    /// Gain, CFO, phase noise and multipath of the link between the Tx and the Rx. Its `delay` is
+    /// the propagation delay, to which the random start time offset is added
+    link: LinkConfig,
+    /// If `Some`, the positions and radio parameters of the Tx and Rx, and the path loss model
+    /// between them
+    geometry: Option<(Node, Node, PathLoss)>,
+    /// The link budget derived from `geometry` at the start, which sets the noise power and the
+    /// link's gain and delay
+    #[serde(skip)]
+    link_budget: Option<LinkBudget>,
+    /// DC offset and IQ imbalance of the Tx's front end
+    tx_impairments: IqImpairments,
+    /// DC offset and IQ imbalance of the Rx's front end
+    rx_impairments: IqImpairments,
+    /// Gain (in dB) the Tx starts with. It is applied before the PA, and can be changed using
+    /// `SimulatedRadioTx::set_gain`
+    tx_gain: f64,
+    /// Nonlinearity of the Tx's power amplifier
+    pa_model: PaModel,
+    /// Analog bandwidth (in Hz) of the Tx's and the Rx's front ends, or `None` for the sample rate
+    tx_bandwidth: Option<f64>,
+    rx_bandwidth: Option<f64>,
+    /// How the Rx's LO settles after `RadioRx::set_freq`
+    lo_settling: LoSettling,
+    /// Gain (in dB) the Rx starts with. It applies to the signal and noise alike, and can be
+    /// changed using `SimulatedRadioRx::set_gain`
+    rx_gain: f64,
+    /// Resolution of the Rx's ADC in bits. The ADC's full scale is [-1, 1] for both I and Q. If
+    /// `None`, the ADC is ideal and neither quantizes nor clips
+    adc_bits: Option<u8>,
+    /// Interference the Rx picks up in addition to the Tx
+    interferers: Vec<Interferer>,
+    /// Antennas of the Tx and the Rx. The gains between them are the link's `mimo`
+    tx_antennas: Antennas,
+    rx_antennas: Antennas,
+    /// If `Some`, the Tx and the Rx are one full-duplex radio, and the Rx hears the Tx only
+    /// through this path rather than through the link
+    self_interference: Option<SelfInterference>,
+    /// Changes to the parameters while the simulation runs, as (time in secs since the start,
+    /// change)
+    timeline: Vec<(f64, ConfigChange)>,
+    /// If `Some`, the radios are paced by the wall clock and can buffer this many seconds of
+    /// samples. Else they produce and consume samples as fast as they are used
+    real_time: Option<f64>,
+}
+
+/// Builds a `RadioSimulatorConfig`. Parameters that are not set keep the defaults given in
+/// `RadioSimulatorConfig::builder`
+#[derive(Clone, Debug)]
+pub struct RadioSimulatorConfigBuilder {
+    config: RadioSimulatorConfig,
+}
+
+impl RadioSimulatorConfig {
+    /// Start building a config. By default, the sample rate is 1 Msps at a frequency of 2.4 GHz,
+    /// there is no start time offset, CFO, phase noise, multipath (i.e. the channel is
+    /// `ChannelModel::Static` with no multipath components) or front end impairments, the
+    /// noise power is -20 dB (i.e. 20 dB SNR for a unit power signal), the Tx has 0 dB gain and
+    /// a linear PA, the Rx has 0 dB gain, an ideal ADC and no interference, both radios have a
+    /// single antenna, the Tx doesn't leak into the Rx, nothing changes while the simulation runs,
+    /// and they don't run in real time
+    pub fn builder() -> RadioSimulatorConfigBuilder {
+        RadioSimulatorConfigBuilder {
+            config: RadioSimulatorConfig::defaults(),
+        }
+    }
+
+    /// The defaults of `builder`, which also fill in the parameters a scenario file leaves out
+    fn defaults() -> Self {
+        RadioSimulatorConfig {
+            max_start_time_offset: 1,
+            samp_rate: 1_000_000,
+            start_freq: 2.4e9,
+            noise_power: -20.,
+            link: LinkConfig::default(),
+            geometry: None,
+            link_budget: None,
+            tx_impairments: IqImpairments::default(),
+            rx_impairments: IqImpairments::default(),
+            tx_gain: 0.,
+            pa_model: PaModel::Linear,
+            rx_gain: 0.,
+            tx_bandwidth: None,
+            rx_bandwidth: None,
+            lo_settling: LoSettling::default(),
+            adc_bits: None,
+            interferers: Vec::new(),
+            tx_antennas: Antennas::default(),
+            rx_antennas: Antennas::default(),
+            self_interference: None,
+            timeline: Vec::new(),
+            real_time: None,
+        }
+    }
+
+    /// The Rx starts receiving the Tx's signal after a number of samples drawn uniformly from
+    /// [0, max_start_time_offset)
+    pub fn max_start_time_offset(&self) -> u64 {
+        self.max_start_time_offset
+    }
+
+    /// Sample rate in samples/sec
+    pub fn samp_rate(&self) -> u64 {
+        self.samp_rate
+    }
+
+    /// Center frequency (in Hz) the radios start at
+    pub fn start_freq(&self) -> f64 {
+        self.start_freq
+    }
+
+    /// Maximum magnitude of the CFO in Hz
+    pub fn max_cfo(&self) -> f64 {
+        self.link.max_cfo
+    }
+
+    /// Standard deviation (in Hz per sample) of the CFO's random walk
+    pub fn cfo_drift(&self) -> f64 {
+        self.link.cfo_drift
+    }
+
+    /// Standard deviation (in radians per sample) of the phase noise
+    pub fn phase_noise(&self) -> f64 {
+        self.link.phase_noise
+    }
+
+    /// Offset (in ppm) of the Rx's sample clock relative to the Tx's
+    pub fn sfo(&self) -> f64 {
+        self.link.sfo
+    }
+
+    /// Rate (in ppm/sec) at which the sample clock offset changes
+    pub fn sfo_drift(&self) -> f64 {
+        self.link.sfo_drift
+    }
+
+    /// Whether the CFO includes the sample clock offset, as if both came from the same oscillator
+    pub fn cfo_from_sfo(&self) -> bool {
+        self.link.cfo_from_sfo
+    }
+
+    /// Noise power in dB, relative to a signal with unit power
+    pub fn noise_power(&self) -> f64 {
+        self.noise_power
+    }
+
+    /// The multipath channel between the Tx and the Rx
+    pub fn channel(&self) -> &ChannelModel {
+        &self.link.channel
+    }
+
+    /// The link between the Tx and the Rx
+    pub fn link(&self) -> &LinkConfig {
+        &self.link
+    }
+
+    /// If the scenario was described by its geometry, the budget of the link between the Tx and
+    /// the Rx at the start
+    pub fn link_budget(&self) -> Option<&LinkBudget> {
+        self.link_budget.as_ref()
+    }
+
+    /// DC offset and IQ imbalance of the Tx's front end
+    pub fn tx_impairments(&self) -> &IqImpairments {
+        &self.tx_impairments
+    }
+
+    /// DC offset and IQ imbalance of the Rx's front end
+    pub fn rx_impairments(&self) -> &IqImpairments {
+        &self.rx_impairments
+    }
+
+    /// Gain (in dB) the Tx starts with
+    pub fn tx_gain(&self) -> f64 {
+        self.tx_gain
+    }
+
+    /// Model of the Tx's power amplifier
+    pub fn pa_model(&self) -> &PaModel {
+        &self.pa_model
+    }
+
+    /// Gain (in dB) the Rx starts with
+    pub fn rx_gain(&self) -> f64 {
+        self.rx_gain
+    }
+
+    /// Analog bandwidth (in Hz) of the Tx's front end, or `None` if it is the sample rate
+    pub fn tx_bandwidth(&self) -> Option<f64> {
+        self.tx_bandwidth
+    }
+
+    /// Analog bandwidth (in Hz) of the Rx's front end, or `None` if it is the sample rate
+    pub fn rx_bandwidth(&self) -> Option<f64> {
+        self.rx_bandwidth
+    }
+
+    /// How the Rx's LO settles after `RadioRx::set_freq`
+    pub fn lo_settling(&self) -> &LoSettling {
+        &self.lo_settling
+    }
+
+    /// Resolution of the Rx's ADC in bits, or `None` if it is ideal
+    pub fn adc_bits(&self) -> Option<u8> {
+        self.adc_bits
+    }
+
+    /// Interference the Rx picks up in addition to the Tx
+    pub fn interferers(&self) -> &[Interferer] {
+        &self.interferers
+    }
+
+    /// Antennas of the Tx
+    pub fn tx_antennas(&self) -> &Antennas {
+        &self.tx_antennas
+    }
+
+    /// Antennas of the Rx
+    pub fn rx_antennas(&self) -> &Antennas {
+        &self.rx_antennas
+    }
+
+    /// Gains from the Tx's antennas to the Rx's
+    pub fn mimo_channel(&self) -> &MimoChannel {
+        &self.link.mimo
+    }
+
+    /// The path through which the Tx leaks into the Rx, if they are one full-duplex radio
+    pub fn self_interference(&self) -> Option<&SelfInterference> {
+        self.self_interference.as_ref()
+    }
+
+    /// Changes to the parameters while the simulation runs, as (time in secs since the start,
+    /// change)
+    pub fn timeline(&self) -> &[(f64, ConfigChange)] {
+        &self.timeline
+    }
+
+    /// If the radios run in real time, the number of seconds of samples they can buffer
+    pub fn real_time(&self) -> Option<f64> {
+        self.real_time
+    }
+}
+
+impl RadioSimulatorConfigBuilder {
+    /// Maximum start time offset between Tx and Rx in samples. Must be at least 1, which means
+    /// there is no offset
+    pub fn max_start_time_offset(mut self, samps: u64) -> Self {
+        self.config.max_start_time_offset = samps;
+        self
+    }
+
+    /// Sample rate in samples/sec
+    pub fn samp_rate(mut self, samp_rate: u64) -> Self {
+        self.config.samp_rate = samp_rate;
+        self
+    }
+
+    /// Center frequency in Hz
+    pub fn start_freq(mut self, freq: f64) -> Self {
+        self.config.start_freq = freq;
+        self
+    }
+
+    /// Maximum magnitude of the CFO in Hz. Must be less than half the sample rate
+    pub fn max_cfo(mut self, cfo: f64) -> Self {
+        self.config.link.max_cfo = cfo;
+        self
+    }
+
+    /// Standard deviation of each step of the CFO's random walk in Hz per sample
+    pub fn cfo_drift(mut self, drift: f64) -> Self {
+        self.config.link.cfo_drift = drift;
+        self
+    }
+
+    /// Standard deviation of the phase noise in radians per sample
+    pub fn phase_noise(mut self, phase_noise: f64) -> Self {
+        self.config.link.phase_noise = phase_noise;
+        self
+    }
+
+    /// Offset (in ppm) of the Rx's sample clock relative to the Tx's. Positive if the Rx's clock
+    /// is faster. See `LinkConfig::sfo`
+    pub fn sfo(mut self, ppm: f64) -> Self {
+        self.config.link.sfo = ppm;
+        self
+    }
+
+    /// Rate (in ppm/sec) at which the sample clock offset changes
+    pub fn sfo_drift(mut self, ppm_per_sec: f64) -> Self {
+        self.config.link.sfo_drift = ppm_per_sec;
+        self
+    }
+
+    /// Derive the carrier and the sample clock from the same oscillator, so that the CFO includes
+    /// the sample clock offset (and its drift)
+    pub fn cfo_from_sfo(mut self, enable: bool) -> Self {
+        self.config.link.cfo_from_sfo = enable;
+        self
+    }
+
+    /// Noise power in dB relative to a unit power signal. Use `f64::NEG_INFINITY` for no noise
+    pub fn noise_power(mut self, noise_power: f64) -> Self {
+        self.config.noise_power = noise_power;
+        self
+    }
+
+    /// The multipath channel between the Tx and the Rx
+    pub fn channel(mut self, channel: ChannelModel) -> Self {
+        self.config.link.channel = channel;
+        self
+    }
+
+    /// Add a static multipath component with the given delay (in secs) and complex gain. If a
+    /// fading channel was selected, it is replaced by a static one
+    pub fn add_multipath(mut self, delay: f64, gain: Complex<f32>) -> Self {
+        match &mut self.config.link.channel {
+            ChannelModel::Static(multipath) => multipath.push((delay, gain)),
+            ChannelModel::Fading { .. } => {
+                self.config.link.channel = ChannelModel::Static(vec![(delay, gain)])
+            }
+        }
+        self
+    }
+
+    /// Place the Tx and the Rx, and derive the noise power (in dBm) and the link's gain and
+    /// propagation delay from their link budget at `start_freq`. This overrides `noise_power`.
+    /// Received samples are then in dBm (plus the Rx's gain): a signal received at -60 dBm with
+    /// 0 dB Rx gain has power 1e-6. If either node moves, the link follows them over time
+    pub fn geometry(mut self, tx: Node, rx: Node, path_loss: PathLoss) -> Self {
+        self.config.geometry = Some((tx, rx, path_loss));
+        self
+    }
+
+    /// DC offset and IQ imbalance of the Tx's front end
+    pub fn tx_impairments(mut self, impairments: IqImpairments) -> Self {
+        self.config.tx_impairments = impairments;
+        self
+    }
+
+    /// DC offset and IQ imbalance of the Rx's front end
+    pub fn rx_impairments(mut self, impairments: IqImpairments) -> Self {
+        self.config.rx_impairments = impairments;
+        self
+    }
+
+    /// Gain (in dB) the Tx starts with
+    pub fn tx_gain(mut self, gain: f64) -> Self {
+        self.config.tx_gain = gain;
+        self
+    }
+
+    /// Model of the Tx's power amplifier
+    pub fn pa_model(mut self, pa: PaModel) -> Self {
+        self.config.pa_model = pa;
+        self
+    }
+
+    /// Gain (in dB) the Rx starts with
+    pub fn rx_gain(mut self, gain: f64) -> Self {
+        self.config.rx_gain = gain;
+        self
+    }
+
+    /// Limit the band the Tx emits to `bandwidth` Hz around its frequency. Must be positive and at
+    /// most the sample rate, which is the default
+    pub fn tx_bandwidth(mut self, bandwidth: f64) -> Self {
+        self.config.tx_bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Limit the band the Rx receives to `bandwidth` Hz around its frequency. Must be positive and
+    /// at most the sample rate, which is the default
+    pub fn rx_bandwidth(mut self, bandwidth: f64) -> Self {
+        self.config.rx_bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// How the Rx's LO settles after `RadioRx::set_freq`. By default, it settles instantly
+    pub fn lo_settling(mut self, settling: LoSettling) -> Self {
+        self.config.lo_settling = settling;
+        self
+    }
+
+    /// Quantize received samples to `bits` bits, clipping the I and Q components to [-1, 1]. Must
+    /// be between 2 and 32
+    pub fn adc_bits(mut self, bits: u8) -> Self {
+        self.config.adc_bits = Some(bits);
+        self
+    }
+
+    /// Add interference to what the Rx picks up. Its power is relative to a unit power signal,
+    /// like `noise_power`
+    pub fn add_interferer(mut self, interferer: Interferer) -> Self {
+        self.config.interferers.push(interferer);
+        self
+    }
+
+    /// Give the Tx several antennas, each of which is a channel of `RadioTxMulti`. By default, it
+    /// has one
+    pub fn tx_antennas(mut self, antennas: Antennas) -> Self {
+        self.config.tx_antennas = antennas;
+        self
+    }
+
+    /// Give the Rx several antennas, each of which is a channel of `RadioRxMulti` with its own
+    /// noise. By default, it has one
+    pub fn rx_antennas(mut self, antennas: Antennas) -> Self {
+        self.config.rx_antennas = antennas;
+        self
+    }
+
+    /// Gains from the Tx's antennas to the Rx's. By default, Rx antenna `i` hears just Tx antenna
+    /// `i`
+    pub fn mimo_channel(mut self, channel: MimoChannel) -> Self {
+        self.config.link.mimo = channel;
+        self
+    }
+
+    /// Make the Tx and the Rx one full-duplex radio, whose Tx leaks into its Rx through
+    /// `self_interference`. The Rx then hears the Tx only through that path, and the parameters
+    /// of the link are unused. To also hear a remote Tx, use a `SimulatedNetwork`. By default,
+    /// they are separate radios
+    pub fn self_interference(mut self, self_interference: SelfInterference) -> Self {
+        self.config.self_interference = Some(self_interference);
+        self
+    }
+
+    /// Apply `change` `time` secs after the simulation starts, from the sample due at that time.
+    /// Changes at the same time are applied in the order they were added
+    pub fn at(mut self, time: f64, change: ConfigChange) -> Self {
+        self.config.timeline.push((time, change));
+        self
+    }
+
+    /// Pace the radios by the wall clock, like real hardware. Each can buffer `buffer_secs`
+    /// seconds of samples. If the caller doesn't receive fast enough, the Rx drops samples and
+    /// reports `RadioError::Overflow`. If it doesn't send fast enough in the middle of a burst,
+    /// the Tx reports `RadioError::Underflow`
+    pub fn real_time(mut self, buffer_secs: f64) -> Self {
+        self.config.real_time = Some(buffer_secs);
+        self
+    }
+
+    /// Check the parameters and return the config
+    pub fn build(mut self) -> Result<RadioSimulatorConfig, RadioError> {
+        if let Some((tx, rx, path_loss)) = &self.config.geometry {
+            let c = &self.config;
+            validate_radio(c.samp_rate, c.start_freq)?;
+            let budget = LinkBudget::new(tx, rx, path_loss, c.start_freq, c.samp_rate)?;
+            self.config.noise_power = budget.noise_power;
+            if tx.is_mobile() || rx.is_mobile() {
+                self.config.link.mobility = Some(Mobility {
+                    tx: tx.clone(),
+                    rx: rx.clone(),
+                    path_loss: path_loss.clone(),
+                });
+                self.config.link.gain = 0.;
+                self.config.link.delay = 0;
+            } else {
+                self.config.link.gain = budget.gain;
+                self.config.link.delay = budget.delay_samples;
+            }
+            self.config.link_budget = Some(budget);
+        }
+
+        let c = &self.config;
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if c.max_start_time_offset == 0 {
+            return invalid("max_start_time_offset must be > 0");
+        }
+        validate_radio(c.samp_rate, c.start_freq)?;
+        validate_noise_power(c.noise_power)?;
+        c.link.validate(c.samp_rate)?;
+        c.tx_impairments.validate()?;
+        c.rx_impairments.validate()?;
+        validate_gain(c.tx_gain)?;
+        c.pa_model.validate()?;
+        validate_gain(c.rx_gain)?;
+        validate_bandwidth(c.tx_bandwidth, c.samp_rate)?;
+        validate_bandwidth(c.rx_bandwidth, c.samp_rate)?;
+        c.lo_settling.validate()?;
+        validate_adc_bits(c.adc_bits)?;
+        for interferer in &c.interferers {
+            interferer.validate(c.samp_rate)?;
+        }
+        c.tx_antennas.validate()?;
+        c.rx_antennas.validate()?;
+        c.link
+            .mimo
+            .validate_antennas(c.tx_antennas.count, c.rx_antennas.count)?;
+        if let Some(self_interference) = &c.self_interference {
+            self_interference.validate(c.samp_rate)?;
+        }
+        validate_timeline(&c.timeline, c.samp_rate)?;
+        validate_real_time(c.real_time, c.samp_rate)?;
+        Ok(self.config)
+    }
+}
+
+impl Serialize for RadioSimulatorConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        RadioSimulatorConfig::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RadioSimulatorConfig {
+    /// Parameters that are missing keep the defaults given in `RadioSimulatorConfig::builder`.
+    /// The parameters derived from `geometry` are derived again
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let config = RadioSimulatorConfig::deserialize(deserializer)?;
+        RadioSimulatorConfigBuilder { config }
+            .build()
+            .map_err(|err| match err {
+                RadioError::InvalidConfig(msg) => serde::de::Error::custom(msg),
+                err => serde::de::Error::custom(err),
+            })
+    }
+}
+
+/// Check the parameters shared by all the radios of a simulation
+pub(super) fn validate_radio(samp_rate: u64, start_freq: f64) -> Result<(), RadioError> {
+    if samp_rate == 0 {
+        return Err(RadioError::InvalidConfig(
+            "samp_rate must be > 0".to_string(),
+        ));
+    }
+    if !(start_freq.is_finite() && start_freq >= 0.) {
+        return Err(RadioError::InvalidConfig(
+            "start_freq must be finite and non-negative".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check the noise power (in dB) of a simulated Rx
+pub(super) fn validate_noise_power(noise_power: f64) -> Result<(), RadioError> {
+    if noise_power.is_nan() || noise_power == f64::INFINITY {
+        return Err(RadioError::InvalidConfig(
+            "noise_power must be a number less than infinity".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check the gain (in dB) of a simulated radio
+pub(super) fn validate_gain(gain: f64) -> Result<(), RadioError> {
+    if !gain.is_finite() {
+        return Err(RadioError::InvalidConfig("gain must be finite".to_string()));
+    }
+    Ok(())
+}
+
+/// Check that a filter bandwidth (in Hz) fits within the sample rate. `None` means the sample rate
+pub(super) fn validate_bandwidth(bandwidth: Option<f64>, samp_rate: u64) -> Result<(), RadioError> {
+    match bandwidth {
+        Some(bandwidth) if !(bandwidth > 0. && bandwidth <= samp_rate as f64) => {
+            Err(RadioError::InvalidConfig(
+                "bandwidth must be positive and at most samp_rate".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check the resolution of a simulated ADC
+pub(super) fn validate_adc_bits(adc_bits: Option<u8>) -> Result<(), RadioError> {
+    match adc_bits {
+        Some(bits) if !(2..=32).contains(&bits) => Err(RadioError::InvalidConfig(
+            "adc_bits must be between 2 and 32".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check the buffer length (in secs) of radios running in real time
+pub(super) fn validate_real_time(
+    buffer_secs: Option<f64>,
+    samp_rate: u64,
+) -> Result<(), RadioError> {
+    match buffer_secs {
+        Some(secs) if !(secs.is_finite() && secs * samp_rate as f64 >= 1.) => {
+            Err(RadioError::InvalidConfig(
+                "the real time buffer must be finite and hold at least one sample".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message of the `InvalidConfig` error that `builder` fails with
+    fn error(builder: RadioSimulatorConfigBuilder) -> String {
+        match builder.build() {
+            Err(RadioError::InvalidConfig(msg)) => msg,
+            other => panic!("expected InvalidConfig, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        let config = RadioSimulatorConfig::builder().build().unwrap();
+        assert!(config.max_start_time_offset() > 0);
+        assert!(config.samp_rate() > 0);
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let builder = RadioSimulatorConfig::builder;
+        assert_eq!(
+            error(builder().max_start_time_offset(0)),
+            "max_start_time_offset must be > 0"
+        );
+        assert_eq!(error(builder().samp_rate(0)), "samp_rate must be > 0");
+        error(builder().start_freq(-1.));
+        error(builder().noise_power(f64::NAN));
+        error(builder().noise_power(f64::INFINITY));
+        error(builder().samp_rate(1_000_000).max_cfo(500e3));
+        error(builder().max_cfo(f64::NAN));
+        error(builder().phase_noise(-1.));
+        error(builder().sfo(1e4));
+        error(builder().add_multipath(-1e-6, Complex::new(1., 0.)));
+        error(builder().tx_gain(f64::INFINITY));
+        error(builder().samp_rate(1_000_000).rx_bandwidth(2e6));
+        error(builder().tx_bandwidth(0.));
+        error(builder().adc_bits(1));
+        error(builder().adc_bits(33));
+        error(builder().samp_rate(1_000_000).real_time(0.5e-6));
+        error(builder().real_time(f64::INFINITY));
+    }
+
+    #[test]
+    fn deserialized_configs_are_validated() {
+        let err = serde_json::from_str::<RadioSimulatorConfig>(r#"{"max_start_time_offset": 0}"#)
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("max_start_time_offset must be > 0"),
+            "{}",
+            err
+        );
+        let err = toml::from_str::<RadioSimulatorConfig>("samp_rate = 0").unwrap_err();
+        assert!(err.to_string().contains("samp_rate must be > 0"), "{}", err);
+        assert!(
+            serde_json::from_str::<RadioSimulatorConfig>(r#"{"max_start_time_offset": 1}"#).is_ok()
+        );
+    }
+}
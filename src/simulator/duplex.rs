@@ -0,0 +1,54 @@
+//! Self-interference of a full-duplex radio, whose Tx leaks into its own Rx. The leakage shares
+//! the device's LO and sample clock, so it has no CFO or sample clock offset, but it is far
+//! stronger than the signals of other transmitters. It passes through the Rx's front end like any
+//! other signal, so it can saturate the ADC.
+
+use crate::RadioError;
+use num::Complex;
+use serde::{Deserialize, Serialize};
+
+/// The path from a radio's Tx to its own Rx
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SelfInterference {
+    /// Isolation (in dB) between the Tx and the Rx, i.e. the attenuation of the direct leakage,
+    /// e.g. through a circulator or between neighbouring antennas
+    pub attenuation: f64,
+    /// Delay (in secs) of the direct leakage
+    pub delay: f64,
+    /// Reflections of the Tx's signal off nearby objects back into the Rx, as (delay in secs,
+    /// complex gain relative to the Tx's signal). Like the direct leakage, each also gets the phase
+    /// shift a carrier at the Rx's frequency accumulates over its delay
+    pub reflections: Vec<(f64, Complex<f32>)>,
+}
+
+impl SelfInterference {
+    /// Check that the parameters make sense for a radio with the given sample rate
+    pub(super) fn validate(&self, samp_rate: u64) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if !self.attenuation.is_finite() {
+            return invalid("self-interference attenuation must be finite");
+        }
+        for (delay, gain) in self.taps() {
+            if !(delay.is_finite() && delay >= 0.) {
+                return invalid("self-interference delays must be finite and non-negative");
+            }
+            // We store this many past samples, like multipath
+            if delay * samp_rate as f64 >= 1e6 {
+                return invalid("self-interference delays must be less than 1e6 samples");
+            }
+            if !(gain.re.is_finite() && gain.im.is_finite()) {
+                return invalid("self-interference gains must be finite");
+            }
+        }
+        Ok(())
+    }
+
+    /// The direct leakage followed by the reflections, as (delay in secs, complex gain)
+    pub(super) fn taps(&self) -> Vec<(f64, Complex<f32>)> {
+        let leakage = 10f32.powf(-self.attenuation as f32 / 20.);
+        std::iter::once((self.delay, Complex::new(leakage, 0.)))
+            .chain(self.reflections.iter().cloned())
+            .collect()
+    }
+}
@@ -0,0 +1,111 @@
+//! Injects faults into simulated radios, to test how the caller recovers from them. Faults are
+//! either scripted to happen once a given number of samples have been received or sent, or happen
+//! at random on any call to `RadioRx::recv` or `RadioTx::send_burst`.
+
+use crate::RadioError;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+
+/// A fault in a simulated radio
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// The call fails with this error, without receiving or sending any samples
+    Error(RadioError),
+    /// This many samples are lost. The Rx skips them, so the timestamp of the next sample jumps
+    /// ahead. The Tx doesn't transmit them, leaving a gap in the burst
+    Drop(u64),
+    /// The device clock jumps by this many seconds (may be negative)
+    TimeJump(f64),
+}
+
+/// Describes which faults a simulated radio suffers, and when. By default, there are none
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultInjector {
+    /// Faults that happen at the first call after the given number of samples have been received
+    /// or sent
+    scripted: Vec<(u64, Fault)>,
+    /// Faults that happen on each call with the given probability
+    random: Vec<(f64, Fault)>,
+    /// Seed for the random faults
+    seed: u64,
+}
+
+impl FaultInjector {
+    /// An injector with no faults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject `fault` in the first call after `samples` samples have been received or sent
+    pub fn at(mut self, samples: u64, fault: Fault) -> Self {
+        self.scripted.push((samples, fault));
+        self
+    }
+
+    /// Inject `fault` in every call with probability `prob`
+    pub fn with_probability(mut self, prob: f64, fault: Fault) -> Self {
+        self.random.push((prob, fault));
+        self
+    }
+
+    /// Seed the random faults, so they happen on the same calls every run. The default seed is 0
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        for (prob, _) in &self.random {
+            if !(*prob >= 0. && *prob <= 1.) {
+                return invalid("fault probabilities must be in [0, 1]");
+            }
+        }
+        let scripted = self.scripted.iter().map(|(_, fault)| fault);
+        for fault in scripted.chain(self.random.iter().map(|(_, fault)| fault)) {
+            if let Fault::TimeJump(jump) = fault {
+                if !jump.is_finite() {
+                    return invalid("time jumps must be finite");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decides which faults happen in each call
+pub(super) struct FaultState {
+    /// Scripted faults that haven't happened yet, in the order they will happen
+    scripted: VecDeque<(u64, Fault)>,
+    random: Vec<(f64, Fault)>,
+    rng: StdRng,
+}
+
+impl FaultState {
+    /// `injector` must have been validated
+    pub fn new(injector: &FaultInjector) -> Self {
+        let mut scripted = injector.scripted.clone();
+        // Stable, so faults at the same sample count happen in the order they were added
+        scripted.sort_by_key(|(samples, _)| *samples);
+        Self {
+            scripted: scripted.into(),
+            random: injector.random.clone(),
+            rng: StdRng::seed_from_u64(injector.seed),
+        }
+    }
+
+    /// Faults that happen in a call made after `samples` samples have been received or sent
+    pub fn next(&mut self, samples: u64) -> Vec<Fault> {
+        let mut faults = Vec::new();
+        while self.scripted.front().is_some_and(|(s, _)| *s <= samples) {
+            faults.push(self.scripted.pop_front().unwrap().1);
+        }
+        for (prob, fault) in &self.random {
+            if self.rng.gen::<f64>() < *prob {
+                faults.push(fault.clone());
+            }
+        }
+        faults
+    }
+}
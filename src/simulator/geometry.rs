@@ -0,0 +1,561 @@
+//! Link budgets from the physical layout of a scenario. Given where the radios are, the power the
+//! Tx radiates, the antenna gains and the Rx's noise figure, `LinkBudget` derives the path loss,
+//! SNR and propagation delay of a link, so that simulations can be compared with link budget
+//! calculations. Powers are in dBm: a link's gain is the power (in dBm) at which the Rx's antenna
+//! receives a unit power signal, and the Rx's noise power is in dBm too.
+//!
+//! Nodes may also move along a `Trajectory`. The link then follows the changing geometry: its
+//! delay, and hence the carrier's phase, change continuously, which causes a Doppler shift.
+
+use super::config::LinkConfig;
+use crate::RadioError;
+use num::Complex;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Speed of light in m/s
+const SPEED_OF_LIGHT: f64 = 299_792_458.;
+/// Boltzmann's constant in J/K
+const BOLTZMANN: f64 = 1.380_649e-23;
+/// Reference temperature (in K) of noise figures
+const NOISE_TEMPERATURE: f64 = 290.;
+/// The geometry of a moving link is computed once every these many samples, and interpolated
+/// linearly in between
+const MOTION_UPDATE_INTERVAL: u64 = 256;
+
+/// How a node moves over time, starting from its `position` at time 0 (when the simulation
+/// starts)
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Trajectory {
+    /// The node doesn't move
+    Static,
+    /// The node moves with this velocity (x, y, z) in m/s
+    ConstantVelocity([f64; 3]),
+    /// (time in secs, position) pairs in increasing order of time. The node moves in a straight
+    /// line at constant speed to each waypoint in turn, reaching it at the given time, and then
+    /// stays at the last one
+    Waypoints(Vec<(f64, [f64; 3])>),
+}
+
+/// A radio in a scenario
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Node {
+    /// Position (x, y, z) in meters at time 0, where z is the height of the antenna above the
+    /// ground
+    pub position: [f64; 3],
+    /// How the node moves from `position`
+    pub trajectory: Trajectory,
+    /// Power (in dBm) the Tx radiates when it sends a unit power signal at 0 dB gain
+    pub tx_power: f64,
+    /// Gain (in dBi) of the antenna
+    pub antenna_gain: f64,
+    /// Noise figure (in dB) of the Rx
+    pub noise_figure: f64,
+}
+
+impl Default for Node {
+    /// A static node at the origin with a 0 dBm Tx, an isotropic antenna and a noiseless Rx front
+    /// end
+    fn default() -> Self {
+        Self {
+            position: [0.; 3],
+            trajectory: Trajectory::Static,
+            tx_power: 0.,
+            antenna_gain: 0.,
+            noise_figure: 0.,
+        }
+    }
+}
+
+/// How the signal's power falls off with distance
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum PathLoss {
+    /// Friis' free space path loss, which grows by 20 dB per decade of distance
+    FreeSpace,
+    /// Free space loss up to `reference_distance` meters, beyond which the loss grows by
+    /// `10 * exponent` dB per decade. The exponent is 2 in free space, about 3 in urban areas and
+    /// 4 to 6 indoors with obstructions
+    LogDistance {
+        exponent: f64,
+        reference_distance: f64,
+    },
+    /// A direct path plus a reflection off flat ground, with reflection coefficient -1. The
+    /// heights of the antennas (the z coordinates) must be positive. Far from the Tx, the loss
+    /// grows by 40 dB per decade
+    TwoRay,
+}
+
+/// The link budget from one node to another
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkBudget {
+    /// Distance (in meters) between the nodes
+    pub distance: f64,
+    /// Path loss in dB
+    pub path_loss: f64,
+    /// Tx power plus the antenna gains minus the path loss, i.e. the power (in dBm) at which the
+    /// Rx receives a unit power signal
+    pub gain: f64,
+    /// Thermal noise power (in dBm) in the Rx's bandwidth (the sample rate), including its noise
+    /// figure
+    pub noise_power: f64,
+    /// SNR (in dB) of a unit power signal
+    pub snr: f64,
+    /// Propagation delay in secs
+    pub delay: f64,
+    /// Propagation delay rounded to the nearest sample
+    pub delay_samples: u64,
+}
+
+/// Moving nodes at either end of a link, whose geometry determines the link's gain, delay and
+/// Doppler shift over time
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mobility {
+    /// The Tx's node. Only its position, trajectory, Tx power and antenna gain matter
+    pub tx: Node,
+    /// The Rx's node. Only its position, trajectory and antenna gain matter
+    pub rx: Node,
+    pub path_loss: PathLoss,
+}
+
+impl Node {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if !self.position.iter().all(|x| x.is_finite()) {
+            return invalid("node positions must be finite");
+        }
+        if !(self.tx_power.is_finite() && self.antenna_gain.is_finite()) {
+            return invalid("tx_power and antenna_gain must be finite");
+        }
+        if !(self.noise_figure.is_finite() && self.noise_figure >= 0.) {
+            return invalid("noise_figure must be finite and non-negative");
+        }
+        match &self.trajectory {
+            Trajectory::Static => {}
+            Trajectory::ConstantVelocity(velocity) => {
+                if !velocity.iter().all(|v| v.is_finite()) {
+                    return invalid("velocities must be finite");
+                }
+            }
+            Trajectory::Waypoints(waypoints) => {
+                let mut prev = 0.;
+                for (time, position) in waypoints {
+                    if !(time.is_finite() && *time > prev) {
+                        return invalid("waypoint times must be finite, positive and increasing");
+                    }
+                    if !position.iter().all(|x| x.is_finite()) {
+                        return invalid("waypoint positions must be finite");
+                    }
+                    prev = *time;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the node ever moves
+    pub fn is_mobile(&self) -> bool {
+        self.trajectory != Trajectory::Static
+    }
+
+    /// Position (x, y, z) in meters at time `time` (in secs)
+    pub fn position_at(&self, time: f64) -> [f64; 3] {
+        let lerp = |from: [f64; 3], to: [f64; 3], frac: f64| {
+            let mut res = from;
+            for (x, (f, t)) in res.iter_mut().zip(from.iter().zip(to.iter())) {
+                *x = f + (t - f) * frac;
+            }
+            res
+        };
+        match &self.trajectory {
+            Trajectory::Static => self.position,
+            Trajectory::ConstantVelocity(velocity) => {
+                let mut res = self.position;
+                for (x, v) in res.iter_mut().zip(velocity.iter()) {
+                    *x += v * time;
+                }
+                res
+            }
+            Trajectory::Waypoints(waypoints) => {
+                let (mut prev_time, mut prev) = (0., self.position);
+                for (wp_time, wp) in waypoints {
+                    if time < *wp_time {
+                        let frac = ((time - prev_time) / (wp_time - prev_time)).max(0.);
+                        return lerp(prev, *wp, frac);
+                    }
+                    prev_time = *wp_time;
+                    prev = *wp;
+                }
+                prev
+            }
+        }
+    }
+}
+
+/// Distance (in meters) between two positions
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+impl PathLoss {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        if let PathLoss::LogDistance {
+            exponent,
+            reference_distance,
+        } = self
+        {
+            if !(exponent.is_finite() && *exponent > 0.) {
+                return Err(RadioError::InvalidConfig(
+                    "the path loss exponent must be finite and positive".to_string(),
+                ));
+            }
+            if !(reference_distance.is_finite() && *reference_distance > 0.) {
+                return Err(RadioError::InvalidConfig(
+                    "the reference distance must be finite and positive".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Path loss (in dB) between antennas at positions `tx` and `rx` at wavelength `wavelength`
+    /// (in meters)
+    fn loss(&self, tx: [f64; 3], rx: [f64; 3], wavelength: f64) -> f64 {
+        let free_space = |d: f64| 20. * (4. * PI * d / wavelength).log10();
+        // The far field formulas break down any closer than this, where the free space loss would
+        // be negative
+        let distance = distance(tx, rx).max(wavelength / (4. * PI));
+        match self {
+            PathLoss::FreeSpace => free_space(distance),
+            PathLoss::LogDistance {
+                exponent,
+                reference_distance,
+            } => {
+                if distance <= *reference_distance {
+                    free_space(distance)
+                } else {
+                    free_space(*reference_distance)
+                        + 10. * exponent * (distance / reference_distance).log10()
+                }
+            }
+            PathLoss::TwoRay => {
+                let (ht, hr) = (tx[2], rx[2]);
+                let horizontal = (distance.powi(2) - (ht - hr).powi(2)).max(0.).sqrt();
+                let reflected = (horizontal.powi(2) + (ht + hr).powi(2)).sqrt();
+                // The reflection is inverted and delayed by the extra distance it travels
+                let phase = 2. * PI * (reflected - distance) / wavelength;
+                let amplitude = 1. / distance - Complex::from_polar(&1., &-phase) / reflected;
+                -20. * (wavelength / (4. * PI) * amplitude.norm()).log10()
+            }
+        }
+    }
+}
+
+impl LinkBudget {
+    /// The budget of the link from `tx` to `rx` at carrier frequency `freq` (in Hz), for radios
+    /// with sample rate `samp_rate` (in samples/sec)
+    pub fn new(
+        tx: &Node,
+        rx: &Node,
+        path_loss: &PathLoss,
+        freq: f64,
+        samp_rate: u64,
+    ) -> Result<Self, RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        tx.validate()?;
+        rx.validate()?;
+        path_loss.validate()?;
+        if !(freq.is_finite() && freq > 0.) {
+            return invalid("the carrier frequency must be finite and positive");
+        }
+        if samp_rate == 0 {
+            return invalid("samp_rate must be > 0");
+        }
+        let distance = distance(tx.position, rx.position);
+        if distance == 0. {
+            return invalid("the nodes of a link must be at different positions");
+        }
+        if *path_loss == PathLoss::TwoRay && !(tx.position[2] > 0. && rx.position[2] > 0.) {
+            return invalid("the two-ray model needs antennas above the ground (z > 0)");
+        }
+
+        let path_loss = path_loss.loss(tx.position, rx.position, SPEED_OF_LIGHT / freq);
+        let gain = tx.tx_power + tx.antenna_gain + rx.antenna_gain - path_loss;
+        // kTB, converted from W to dBm
+        let thermal = BOLTZMANN * NOISE_TEMPERATURE * samp_rate as f64 * 1e3;
+        let noise_power = 10. * thermal.log10() + rx.noise_figure;
+        let delay = distance / SPEED_OF_LIGHT;
+        Ok(Self {
+            distance,
+            path_loss,
+            gain,
+            noise_power,
+            snr: gain - noise_power,
+            delay,
+            delay_samples: (delay * samp_rate as f64).round() as u64,
+        })
+    }
+
+    /// A link with this budget's gain and delay, and no other impairments. For moving nodes, use
+    /// `Mobility` instead
+    pub fn link_config(&self) -> LinkConfig {
+        LinkConfig {
+            gain: self.gain,
+            delay: self.delay_samples,
+            ..LinkConfig::default()
+        }
+    }
+}
+
+impl Mobility {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        self.tx.validate()?;
+        self.rx.validate()?;
+        self.path_loss.validate()
+    }
+
+    /// A link whose gain and delay follow the nodes, with no other impairments
+    pub fn link_config(&self) -> LinkConfig {
+        LinkConfig {
+            mobility: Some(self.clone()),
+            ..LinkConfig::default()
+        }
+    }
+
+    /// Gain (in dB) and propagation delay (in secs) at time `time` (in secs) and frequency `freq`
+    /// (in Hz)
+    fn at(&self, time: f64, freq: f64) -> (f64, f64) {
+        let (tx, rx) = (self.tx.position_at(time), self.rx.position_at(time));
+        let loss = self.path_loss.loss(tx, rx, SPEED_OF_LIGHT / freq);
+        let gain = self.tx.tx_power + self.tx.antenna_gain + self.rx.antenna_gain - loss;
+        (gain, distance(tx, rx) / SPEED_OF_LIGHT)
+    }
+}
+
+/// Follows the geometry of a moving link as the simulation runs
+pub(super) struct Motion {
+    mobility: Mobility,
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// The frequency and interval for which `start` and `end` were computed, identified by the
+    /// index of its first sample
+    cached: Option<(f64, u64)>,
+    /// (propagation delay in samples, amplitude gain) at the start and end of the interval
+    start: (f64, f64),
+    end: (f64, f64),
+    /// The carrier's phase (in radians) at the start of the interval, and its change per sample
+    phase: f64,
+    phase_step: f64,
+    /// Phasor of the carrier's phase at sample `next`, which is rotated by `step` one sample at a
+    /// time
+    phasor: Complex<f64>,
+    step: Complex<f64>,
+    next: u64,
+}
+
+impl Motion {
+    /// `mobility` must have been validated
+    pub fn new(mobility: &Mobility, samp_rate: u64) -> Self {
+        Self {
+            mobility: mobility.clone(),
+            samp_rate: samp_rate as f64,
+            cached: None,
+            start: (0., 0.),
+            end: (0., 0.),
+            phase: 0.,
+            phase_step: 0.,
+            phasor: Complex::new(1., 0.),
+            step: Complex::new(1., 0.),
+            next: 0,
+        }
+    }
+
+    /// Append the propagation delay (in samples) at each of the `len` samples of the Rx from the
+    /// `index`^th to `delays`, and the link's complex gain to `gains`. The gain's phase is that of
+    /// the delayed carrier, so a changing delay causes a Doppler shift. Fastest when called for
+    /// consecutive blocks
+    pub fn block(
+        &mut self,
+        index: u64,
+        len: usize,
+        freq: f64,
+        delays: &mut Vec<f64>,
+        gains: &mut Vec<Complex<f32>>,
+    ) {
+        let end = index + len as u64;
+        let mut index = index;
+        while index < end {
+            let first = index - index % MOTION_UPDATE_INTERVAL;
+            let offset = (index - first) as f64;
+            if self.cached != Some((freq, first)) {
+                let (mobility, samp_rate) = (&self.mobility, self.samp_rate);
+                let exact = |index: u64| {
+                    let (gain, delay) = mobility.at(index as f64 / samp_rate, freq);
+                    (delay * samp_rate, 10f64.powf(gain / 20.))
+                };
+                self.start = exact(first);
+                self.end = exact(first + MOTION_UPDATE_INTERVAL);
+                self.cached = Some((freq, first));
+                let radians = -2. * PI * freq / self.samp_rate;
+                self.phase = (radians * self.start.0) % (2. * PI);
+                self.phase_step =
+                    radians * (self.end.0 - self.start.0) / MOTION_UPDATE_INTERVAL as f64;
+                self.step = Complex::from_polar(&1., &self.phase_step);
+                self.next = index;
+                self.phasor = Complex::from_polar(&1., &(self.phase + self.phase_step * offset));
+            } else if self.next != index {
+                self.next = index;
+                self.phasor = Complex::from_polar(&1., &(self.phase + self.phase_step * offset));
+            }
+
+            // Interpolate linearly across the interval
+            let interval_end = end.min(first + MOTION_UPDATE_INTERVAL);
+            for index in index..interval_end {
+                let frac = (index - first) as f64 / MOTION_UPDATE_INTERVAL as f64;
+                delays.push(self.start.0 + (self.end.0 - self.start.0) * frac);
+                let gain = (self.start.1 + (self.end.1 - self.start.1) * frac) as f32;
+                gains.push(Complex::new(self.phasor.re as f32, self.phasor.im as f32) * gain);
+                self.phasor *= self.step;
+            }
+            self.next = interval_end;
+            index = interval_end;
+        }
+    }
+
+    /// Gain (in dB), propagation delay (in secs) and Doppler shift (in Hz) at the `index`^th
+    /// sample of the Rx
+    pub fn truth(&self, index: u64, freq: f64) -> (f64, f64, f64) {
+        let time = index as f64 / self.samp_rate;
+        let (gain, delay) = self.mobility.at(time, freq);
+        // Differentiate the delay numerically over a short time
+        let dt = 1e-3;
+        let (_, later) = self.mobility.at(time + dt, freq);
+        (gain, delay, -freq * (later - delay) / dt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FREQ: f64 = 2.4e9;
+    const SAMP_RATE: u64 = 1_000_000;
+
+    /// A node at `position` with an isotropic antenna and a 0 dBm Tx
+    fn node(position: [f64; 3]) -> Node {
+        Node {
+            position,
+            ..Node::default()
+        }
+    }
+
+    /// Free space loss (in dB) at distance `d` (in meters) and `FREQ`, from the textbook formula
+    /// in meters and Hz
+    fn friis(d: f64) -> f64 {
+        20. * d.log10() + 20. * FREQ.log10() - 147.55
+    }
+
+    fn loss(path_loss: PathLoss, tx: [f64; 3], rx: [f64; 3]) -> f64 {
+        let budget = LinkBudget::new(&node(tx), &node(rx), &path_loss, FREQ, SAMP_RATE).unwrap();
+        budget.path_loss
+    }
+
+    #[test]
+    fn path_loss_matches_closed_forms() {
+        let (tx, rx) = ([0., 0., 10.], [1000., 0., 10.]);
+        assert!((loss(PathLoss::FreeSpace, tx, rx) - friis(1000.)).abs() < 0.01);
+        assert!((loss(PathLoss::FreeSpace, tx, rx) - 100.05).abs() < 0.01);
+
+        // Free space up to 10 m, and then 30 dB per decade
+        let log_distance = PathLoss::LogDistance {
+            exponent: 3.,
+            reference_distance: 10.,
+        };
+        assert!((loss(log_distance.clone(), tx, [5., 0., 10.]) - friis(5.)).abs() < 0.01);
+        let expected = friis(10.) + 60.;
+        assert!((loss(log_distance, tx, rx) - expected).abs() < 0.01);
+
+        // Far beyond the crossover distance 4 pi ht hr / wavelength (10 km here), the two-ray loss
+        // is 40 log10(d) - 20 log10(ht hr)
+        let far = [100e3, 0., 10.];
+        let expected = 40. * 100e3f64.log10() - 20. * 100f64.log10();
+        assert!((loss(PathLoss::TwoRay, tx, far) - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn link_budget_adds_up() {
+        let tx = Node {
+            tx_power: 20.,
+            antenna_gain: 3.,
+            ..node([0.; 3])
+        };
+        let rx = Node {
+            antenna_gain: 2.,
+            noise_figure: 5.,
+            ..node([3000., 0., 0.])
+        };
+        let budget = LinkBudget::new(&tx, &rx, &PathLoss::FreeSpace, FREQ, SAMP_RATE).unwrap();
+        assert!((budget.path_loss - friis(3000.)).abs() < 0.01);
+        assert!((budget.gain - (25. - budget.path_loss)).abs() < 1e-9);
+        // kTB is -174 dBm/Hz at 290 K, so -114 dBm over 1 MHz
+        assert!((budget.noise_power - (-173.98 + 60. + 5.)).abs() < 0.01);
+        assert!((budget.snr - (budget.gain - budget.noise_power)).abs() < 1e-9);
+        // 3 km takes 10 us
+        assert!((budget.delay - 3000. / SPEED_OF_LIGHT).abs() < 1e-15);
+        assert_eq!(budget.delay_samples, 10);
+        assert_eq!(budget.link_config().delay, 10);
+    }
+
+    #[test]
+    fn moving_node_changes_delay_gain_and_doppler() {
+        // The Rx starts 100 m from the Tx and moves away at 30 m/s
+        let mobility = Mobility {
+            tx: node([0.; 3]),
+            rx: Node {
+                trajectory: Trajectory::ConstantVelocity([30., 0., 0.]),
+                ..node([100., 0., 0.])
+            },
+            path_loss: PathLoss::FreeSpace,
+        };
+        let mut motion = Motion::new(&mobility, SAMP_RATE);
+        let (mut delays, mut gains) = (Vec::new(), Vec::new());
+        // Over a second, in blocks
+        let len = SAMP_RATE as usize;
+        for index in (0..len).step_by(10_000) {
+            motion.block(index as u64, 10_000, FREQ, &mut delays, &mut gains);
+        }
+        assert_eq!((delays.len(), gains.len()), (len, len));
+
+        // -f d(tau)/dt
+        let doppler = -FREQ * 30. / SPEED_OF_LIGHT;
+        let samp_rate = SAMP_RATE as f64;
+        for index in (0..len).step_by(997) {
+            let distance = 100. + 30. * index as f64 / samp_rate;
+            let delay = distance / SPEED_OF_LIGHT * samp_rate;
+            assert!((delays[index] - delay).abs() < 1e-6);
+            let gain = 10f64.powf(-friis(distance) / 20.) as f32;
+            assert!((gains[index].norm() / gain - 1.).abs() < 1e-3);
+            let step = (gains[index + 1] / gains[index]).arg() as f64;
+            assert!((step * samp_rate / (2. * PI) - doppler).abs() < 0.1);
+
+            let (gain_db, delay_secs, truth_doppler) = motion.truth(index as u64, FREQ);
+            assert!((gain_db + friis(distance)).abs() < 0.01);
+            assert!((delay_secs * samp_rate - delay).abs() < 1e-6);
+            assert!((truth_doppler - doppler).abs() < 0.1);
+        }
+        // The delay grows by 0.1 samples, and the gain falls as the distance grows from 100 m to
+        // 130 m
+        assert!((delays[len - 1] - delays[0] - 0.1).abs() < 1e-3);
+        let fall = 20. * (gains[0].norm() / gains[len - 1].norm()).log10();
+        assert!((fall - 20. * 1.3f32.log10()).abs() < 0.01);
+    }
+}
@@ -0,0 +1,150 @@
+//! Imperfections of a radio's analog front end. `IqImpairments` models the DC offset (e.g. due to
+//! LO leakage) and the gain and phase mismatch between the I and Q branches of a mixer. They can be
+//! applied to the Tx or the Rx path of a simulated radio.
+
+use crate::RadioError;
+use num::{Complex, Zero};
+use serde::{Deserialize, Serialize};
+
+/// DC offset and IQ imbalance of one path (Tx or Rx) of a radio
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IqImpairments {
+    /// Constant added to every sample, relative to a unit power signal
+    pub dc_offset: Complex<f32>,
+    /// Gain (in dB) of the Q branch relative to the I branch
+    pub amplitude_imbalance: f64,
+    /// Error (in radians) in the 90 degree phase difference between the I and Q branches
+    pub phase_imbalance: f64,
+}
+
+impl Default for IqImpairments {
+    /// A perfect front end
+    fn default() -> Self {
+        Self {
+            dc_offset: Complex::zero(),
+            amplitude_imbalance: 0.,
+            phase_imbalance: 0.,
+        }
+    }
+}
+
+impl IqImpairments {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if !(self.dc_offset.re.is_finite() && self.dc_offset.im.is_finite()) {
+            return invalid("dc_offset must be finite");
+        }
+        if !self.amplitude_imbalance.is_finite() {
+            return invalid("amplitude_imbalance must be finite");
+        }
+        if !(self.phase_imbalance.is_finite()
+            && self.phase_imbalance.abs() < std::f64::consts::FRAC_PI_2)
+        {
+            return invalid("phase_imbalance must be in (-pi/2, pi/2)");
+        }
+        Ok(())
+    }
+}
+
+/// Applies `IqImpairments` to samples
+#[derive(Clone, Debug)]
+pub(super) struct IqDistortion {
+    dc_offset: Complex<f32>,
+    /// Gain of the signal
+    direct: Complex<f32>,
+    /// Gain of the signal's mirror image (its complex conjugate)
+    image: Complex<f32>,
+}
+
+impl IqDistortion {
+    pub fn new(impairments: &IqImpairments) -> Self {
+        // With Q branch gain g and phase error p, the output is
+        // direct * x + image * conj(x), where direct = (1 + g e^-jp) / 2 and image = (1 - g e^jp) / 2
+        let g = 10f32.powf(impairments.amplitude_imbalance as f32 / 20.);
+        let p = impairments.phase_imbalance as f32;
+        Self {
+            dc_offset: impairments.dc_offset,
+            direct: (Complex::new(1., 0.) + Complex::from_polar(&g, &-p)) / 2.,
+            image: (Complex::new(1., 0.) - Complex::from_polar(&g, &p)) / 2.,
+        }
+    }
+
+    pub fn process(&self, samp: Complex<f32>) -> Complex<f32> {
+        self.direct * samp + self.image * samp.conj() + self.dc_offset
+    }
+
+    /// Apply the distortion to a block of samples, in place
+    pub fn process_block(&self, samps: &mut [Complex<f32>]) {
+        // A perfect front end is common, and leaves the samples unchanged
+        if self.image.is_zero() && self.dc_offset.is_zero() && self.direct == Complex::new(1., 0.) {
+            return;
+        }
+        for samp in samps.iter_mut() {
+            *samp = self.process(*samp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    /// Correlation of `samps` with a complex exponential at `freq` cycles/sample, i.e. the
+    /// amplitude of that frequency in `samps`
+    fn amplitude(samps: &[Complex<f32>], freq: f64) -> Complex<f64> {
+        let sum: Complex<f64> = samps
+            .iter()
+            .enumerate()
+            .map(|(n, s)| {
+                let s = Complex::new(s.re as f64, s.im as f64);
+                s * Complex::from_polar(&1., &(-2. * PI * freq * n as f64))
+            })
+            .sum();
+        sum / samps.len() as f64
+    }
+
+    #[test]
+    fn perfect_front_end_changes_nothing() {
+        let distortion = IqDistortion::new(&IqImpairments::default());
+        let samp = Complex::new(0.3, -0.7);
+        assert_eq!(distortion.process(samp), samp);
+    }
+
+    #[test]
+    fn image_rejection_and_dc_offset_match_the_impairments() {
+        let impairments = IqImpairments {
+            dc_offset: Complex::new(0.05, -0.02),
+            amplitude_imbalance: 1.,
+            phase_imbalance: 0.05,
+        };
+        let distortion = IqDistortion::new(&impairments);
+        // A tone that completes a whole number of cycles, so that the tone, its image and DC are
+        // orthogonal
+        let (len, freq) = (1000, 0.1);
+        let mut samps: Vec<_> = (0..len)
+            .map(|n| Complex::from_polar(&1., &(2. * PI * freq * n as f64)))
+            .map(|s| Complex::new(s.re as f32, s.im as f32))
+            .collect();
+        distortion.process_block(&mut samps);
+
+        // The image rejection ratio of a mixer with Q branch gain g and phase error p
+        let (g, p) = (10f64.powf(1. / 20.), 0.05f64);
+        let irr = (1. + 2. * g * p.cos() + g * g) / (1. - 2. * g * p.cos() + g * g);
+        let tone = amplitude(&samps, freq).norm_sqr();
+        let image = amplitude(&samps, -freq).norm_sqr();
+        let measured = 10. * (tone / image).log10();
+        assert!(
+            (measured - 10. * irr.log10()).abs() < 0.01,
+            "{} dB",
+            measured
+        );
+        // About 24 dB
+        assert!((measured - 24.).abs() < 0.5);
+
+        let dc = amplitude(&samps, 0.);
+        assert!((dc.re - 0.05).abs() < 1e-5 && (dc.im + 0.02).abs() < 1e-5);
+    }
+}
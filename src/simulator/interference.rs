@@ -0,0 +1,448 @@
+//! Interference that a simulated Rx picks up in addition to its transmitters and thermal noise:
+//! tones, chirps, bursty wideband noise, impulsive noise and replayed captures. An interferer is
+//! placed relative to the frequency the Rx is tuned to, so it keeps its place in the received band
+//! when the Rx retunes, and it drops out when it is outside the Rx's analog bandwidth.
+
+use crate::RadioError;
+use num::{Complex, Zero};
+use rand::{distributions::Distribution, Rng};
+use rand_distr::{Exp, Normal};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// A source of interference. Powers are in dB relative to a unit power signal, like the Rx's noise
+/// power, and are those at the Rx's antenna. Frequency offsets (in Hz) are relative to the Rx's
+/// current frequency
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum Interferer {
+    /// A continuous wave
+    Tone { power: f64, offset: f64 },
+    /// A tone whose frequency sweeps linearly from `offset - bandwidth / 2` to
+    /// `offset + bandwidth / 2` every `period` secs, and then starts over
+    Chirp {
+        power: f64,
+        offset: f64,
+        bandwidth: f64,
+        period: f64,
+    },
+    /// White noise over the whole band of the Rx, which switches on and off like the packets of a
+    /// wideband transmitter. The on and off times are exponentially distributed, with means
+    /// `mean_on` and `mean_off` secs
+    BurstyNoise {
+        power: f64,
+        mean_on: f64,
+        mean_off: f64,
+    },
+    /// Impulses of complex Gaussian noise, each one sample long, arriving at random at an average
+    /// `rate` per second
+    Impulsive { power: f64, rate: f64 },
+    /// A capture at the Rx's sample rate, played in a loop at `offset` and scaled to average power
+    /// `power`
+    Replay {
+        power: f64,
+        offset: f64,
+        samples: Vec<Complex<f32>>,
+    },
+}
+
+impl Interferer {
+    /// Check that the parameters make sense for an Rx with the given sample rate
+    pub(super) fn validate(&self, samp_rate: u64) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        let positive = |x: f64| x.is_finite() && x > 0.;
+        let (power, offset) = match self {
+            Interferer::Tone { power, offset } => (*power, *offset),
+            Interferer::Chirp {
+                power,
+                offset,
+                bandwidth,
+                period,
+            } => {
+                if !(bandwidth.is_finite() && *bandwidth >= 0.) {
+                    return invalid("chirp bandwidth must be finite and non-negative");
+                }
+                if !positive(*period) {
+                    return invalid("chirp period must be finite and positive");
+                }
+                (*power, *offset)
+            }
+            Interferer::BurstyNoise {
+                power,
+                mean_on,
+                mean_off,
+            } => {
+                if !(positive(*mean_on) && positive(*mean_off)) {
+                    return invalid("mean_on and mean_off must be finite and positive");
+                }
+                (*power, 0.)
+            }
+            Interferer::Impulsive { power, rate } => {
+                if !(positive(*rate) && *rate <= samp_rate as f64) {
+                    return invalid("impulse rate must be positive and at most samp_rate");
+                }
+                (*power, 0.)
+            }
+            Interferer::Replay {
+                power,
+                offset,
+                samples,
+            } => {
+                if !samples.iter().all(|s| s.re.is_finite() && s.im.is_finite()) {
+                    return invalid("replayed samples must be finite");
+                }
+                if samples.iter().all(|s| s.is_zero()) {
+                    return invalid("replayed samples must not all be zero");
+                }
+                (*power, *offset)
+            }
+        };
+        if !power.is_finite() {
+            return invalid("interferer power must be finite");
+        }
+        if !offset.is_finite() {
+            return invalid("interferer offset must be finite");
+        }
+        Ok(())
+    }
+}
+
+/// Generates an `Interferer`'s signal
+pub(super) struct InterfererState {
+    kind: Kind,
+    /// Amplitude gain
+    amplitude: f32,
+    /// Frequency (in Hz, relative to the Rx's frequency) and phase (in radians) of the carrier
+    offset: f64,
+    phase: f64,
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+}
+
+enum Kind {
+    Tone,
+    Chirp {
+        /// Change in frequency (in Hz) per sample
+        sweep: f64,
+        /// Length of a sweep and the position in it, in samples
+        period: u64,
+        pos: u64,
+        /// Half the swept bandwidth in Hz
+        half_bandwidth: f64,
+    },
+    BurstyNoise {
+        noise: Normal<f32>,
+        on_time: Exp<f64>,
+        off_time: Exp<f64>,
+        on: bool,
+        /// Samples left till the noise switches on or off
+        remaining: u64,
+    },
+    Impulsive {
+        noise: Normal<f32>,
+        /// Number of samples between impulses
+        gap: Exp<f64>,
+        /// Samples left till the next impulse
+        remaining: u64,
+    },
+    Replay {
+        samples: Vec<Complex<f32>>,
+        pos: usize,
+    },
+}
+
+impl InterfererState {
+    /// Random parameters (phases, the time of the first burst or impulse) are drawn from `rng`.
+    /// `interferer` must have been validated
+    pub fn new<R: Rng>(interferer: &Interferer, samp_rate: u64, rng: &mut R) -> Self {
+        let samp_rate = samp_rate as f64;
+        let (power, offset, kind) = match interferer {
+            Interferer::Tone { power, offset } => (*power, *offset, Kind::Tone),
+            Interferer::Chirp {
+                power,
+                offset,
+                bandwidth,
+                period,
+            } => {
+                let period = ((period * samp_rate) as u64).max(1);
+                let kind = Kind::Chirp {
+                    sweep: bandwidth / period as f64,
+                    period,
+                    pos: 0,
+                    half_bandwidth: bandwidth / 2.,
+                };
+                (*power, *offset, kind)
+            }
+            Interferer::BurstyNoise {
+                power,
+                mean_on,
+                mean_off,
+            } => {
+                let off_time = Exp::new(1. / (mean_off * samp_rate)).unwrap();
+                let kind = Kind::BurstyNoise {
+                    noise: Normal::new(0., 0.5f32.sqrt()).unwrap(),
+                    on_time: Exp::new(1. / (mean_on * samp_rate)).unwrap(),
+                    on: false,
+                    remaining: off_time.sample(rng) as u64,
+                    off_time,
+                };
+                (*power, 0., kind)
+            }
+            Interferer::Impulsive { power, rate } => {
+                let gap = Exp::new(rate / samp_rate).unwrap();
+                let kind = Kind::Impulsive {
+                    noise: Normal::new(0., 0.5f32.sqrt()).unwrap(),
+                    remaining: gap.sample(rng) as u64,
+                    gap,
+                };
+                (*power, 0., kind)
+            }
+            Interferer::Replay {
+                power,
+                offset,
+                samples,
+            } => {
+                // Normalize to unit power
+                let capture_power = samples.iter().map(|s| s.norm_sqr()).sum::<f32>();
+                let norm = (samples.len() as f32 / capture_power).sqrt();
+                let kind = Kind::Replay {
+                    samples: samples.iter().map(|s| s * norm).collect(),
+                    pos: 0,
+                };
+                (*power, *offset, kind)
+            }
+        };
+        Self {
+            kind,
+            amplitude: 10f32.powf(power as f32 / 20.),
+            offset,
+            phase: rng.gen::<f64>() * 2. * PI,
+            samp_rate,
+        }
+    }
+
+    /// Add the next `out.len()` samples of interference, as received by an Rx with analog
+    /// bandwidth `bandwidth` (in Hz)
+    pub fn add_block<R: Rng>(&mut self, out: &mut [Complex<f32>], bandwidth: f64, rng: &mut R) {
+        // The Rx only receives frequencies within its bandwidth, which is at most the sample rate
+        let band_edge = bandwidth.min(self.samp_rate) / 2.;
+        let shift = self.offset;
+        let in_band = |f: f64| f.abs() < band_edge;
+        let amplitude = self.amplitude;
+        let radians = 2. * PI / self.samp_rate;
+        match &mut self.kind {
+            Kind::Tone => {
+                if in_band(shift) {
+                    let rotation = Complex::from_polar(&1., &(radians * shift));
+                    let mut phasor = Complex::from_polar(&1., &self.phase);
+                    for samp in out.iter_mut() {
+                        *samp += Complex::new(phasor.re as f32, phasor.im as f32) * amplitude;
+                        phasor *= rotation;
+                    }
+                    self.phase = phasor.arg();
+                } else {
+                    self.phase = (self.phase + radians * shift * out.len() as f64) % (2. * PI);
+                }
+            }
+            Kind::Chirp {
+                sweep,
+                period,
+                pos,
+                half_bandwidth,
+            } => {
+                for samp in out.iter_mut() {
+                    let f = shift - *half_bandwidth + *sweep * *pos as f64;
+                    self.phase = (self.phase + radians * f) % (2. * PI);
+                    if in_band(f) {
+                        *samp += Complex::from_polar(&amplitude, &(self.phase as f32));
+                    }
+                    *pos = (*pos + 1) % *period;
+                }
+            }
+            Kind::BurstyNoise {
+                noise,
+                on_time,
+                off_time,
+                on,
+                remaining,
+            } => {
+                let mut pos = 0;
+                while pos < out.len() {
+                    if *remaining == 0 {
+                        *on = !*on;
+                        let time = if *on { &*on_time } else { &*off_time };
+                        *remaining = (time.sample(rng) as u64).max(1);
+                    }
+                    let chunk = (*remaining).min((out.len() - pos) as u64) as usize;
+                    if *on {
+                        for samp in &mut out[pos..pos + chunk] {
+                            let n = Complex::new(noise.sample(rng), noise.sample(rng));
+                            *samp += n * amplitude;
+                        }
+                    }
+                    *remaining -= chunk as u64;
+                    pos += chunk;
+                }
+            }
+            Kind::Impulsive {
+                noise,
+                gap,
+                remaining,
+            } => {
+                let mut pos = 0;
+                loop {
+                    let left = (out.len() - pos) as u64;
+                    if *remaining >= left {
+                        *remaining -= left;
+                        break;
+                    }
+                    pos += *remaining as usize;
+                    let n = Complex::new(noise.sample(rng), noise.sample(rng));
+                    out[pos] += n * amplitude;
+                    pos += 1;
+                    *remaining = gap.sample(rng) as u64;
+                }
+            }
+            Kind::Replay { samples, pos } => {
+                let rotation = Complex::from_polar(&1., &(radians * shift));
+                let mut phasor = Complex::from_polar(&1., &self.phase);
+                for samp in out.iter_mut() {
+                    if in_band(shift) {
+                        let carrier = Complex::new(phasor.re as f32, phasor.im as f32);
+                        *samp += samples[*pos] * carrier * amplitude;
+                    }
+                    phasor *= rotation;
+                    *pos = (*pos + 1) % samples.len();
+                }
+                self.phase = phasor.arg();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SAMP_RATE: u64 = 1_000_000;
+
+    /// `len` samples of `interferer` as received with analog bandwidth `bandwidth`, in two blocks
+    /// that must continue the same signal
+    fn receive(interferer: &Interferer, bandwidth: f64, len: usize) -> Vec<Complex<f32>> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = InterfererState::new(interferer, SAMP_RATE, &mut rng);
+        let mut out = vec![Complex::zero(); len];
+        let (first, second) = out.split_at_mut(len / 3);
+        state.add_block(first, bandwidth, &mut rng);
+        state.add_block(second, bandwidth, &mut rng);
+        out
+    }
+
+    /// Frequency (in Hz) of the signal between each pair of consecutive samples
+    fn freqs(samps: &[Complex<f32>]) -> Vec<f64> {
+        samps
+            .windows(2)
+            .map(|pair| (pair[1] / pair[0]).arg() as f64 * SAMP_RATE as f64 / (2. * PI))
+            .collect()
+    }
+
+    /// Average power of `samps`
+    fn power(samps: &[Complex<f32>]) -> f32 {
+        samps.iter().map(|s| s.norm_sqr()).sum::<f32>() / samps.len() as f32
+    }
+
+    /// A -10 dB tone 100 kHz above the Rx's frequency
+    const TONE: Interferer = Interferer::Tone {
+        power: -10.,
+        offset: 1e5,
+    };
+
+    #[test]
+    fn tone_is_at_its_offset() {
+        let out = receive(&TONE, SAMP_RATE as f64, 1000);
+        assert!(out.iter().all(|samp| (samp.norm_sqr() - 0.1).abs() < 1e-4));
+        assert!(freqs(&out).iter().all(|f| (f - 1e5).abs() < 1.));
+    }
+
+    #[test]
+    fn tone_outside_bandwidth_is_blocked() {
+        let out = receive(&TONE, 150e3, 1000);
+        assert!(out.iter().all(|samp| samp.is_zero()));
+        let out = receive(&TONE, 250e3, 1000);
+        assert!(out.iter().all(|samp| (samp.norm_sqr() - 0.1).abs() < 1e-4));
+    }
+
+    #[test]
+    fn chirp_sweeps_its_band() {
+        // Sweeps from -100 kHz to 100 kHz every 1000 samples, i.e. by 200 Hz a sample
+        let chirp = Interferer::Chirp {
+            power: 0.,
+            offset: 0.,
+            bandwidth: 2e5,
+            period: 1e-3,
+        };
+        let out = receive(&chirp, SAMP_RATE as f64, 2500);
+        assert!(out.iter().all(|samp| (samp.norm_sqr() - 1.).abs() < 1e-4));
+        for (i, f) in freqs(&out).iter().enumerate() {
+            let expected = -1e5 + 200. * ((i + 1) % 1000) as f64;
+            assert!((f - expected).abs() < 1., "{} Hz at {}", f, i);
+        }
+        // The Rx only hears the part of the sweep within its bandwidth
+        let out = receive(&chirp, 1e5, 2500);
+        let heard: Vec<_> = (0..1000).filter(|&i| !out[i].is_zero()).collect();
+        assert_eq!(heard, (251..750).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bursty_noise_is_on_for_its_share_of_the_time() {
+        let bursty = Interferer::BurstyNoise {
+            power: 0.,
+            mean_on: 1e-3,
+            mean_off: 3e-3,
+        };
+        let out = receive(&bursty, SAMP_RATE as f64, 1_000_000);
+        let on: Vec<_> = out.iter().filter(|s| !s.is_zero()).cloned().collect();
+        let share = on.len() as f32 / out.len() as f32;
+        assert!((share - 0.25).abs() < 0.05, "on {}", share);
+        assert!((power(&on) - 1.).abs() < 0.02);
+    }
+
+    #[test]
+    fn impulses_arrive_at_their_rate() {
+        let impulsive = Interferer::Impulsive {
+            power: 10.,
+            rate: 1000.,
+        };
+        let out = receive(&impulsive, SAMP_RATE as f64, 1_000_000);
+        let impulses: Vec<_> = out.iter().filter(|s| !s.is_zero()).cloned().collect();
+        // About 1000 in a second, give or take a few standard deviations
+        assert!(
+            (impulses.len() as f32 - 1000.).abs() < 150.,
+            "{}",
+            impulses.len()
+        );
+        assert!((power(&impulses) - 10.).abs() < 1.5);
+    }
+
+    #[test]
+    fn replay_loops_the_capture() {
+        let capture = vec![
+            Complex::new(1., 0.),
+            Complex::new(0., -2.),
+            Complex::new(0.5, 0.5),
+        ];
+        let replay = Interferer::Replay {
+            power: -10.,
+            offset: 0.,
+            samples: capture.clone(),
+        };
+        let out = receive(&replay, SAMP_RATE as f64, 300);
+        assert!((power(&out) - 0.1).abs() < 1e-4);
+        // Scaled and rotated by the same random phase throughout
+        let gain = out[0] / capture[0];
+        for (i, samp) in out.iter().enumerate() {
+            assert!((samp - capture[i % 3] * gain).norm() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,845 @@
+//! The path from one simulated Tx to one simulated Rx. A `Link` buffers the samples its Tx has
+//! sent and applies the link's delay, sampling frequency offset, motion, the difference between
+//! the radios' tunings, multipath, CFO, phase noise and gain to them, and then the MIMO gains from
+//! the Tx's antennas to the Rx's. Whenever the Tx hasn't sent anything for a given time, it is
+//! silent.
+
+use super::channel::{ChannelModel, MultipathChannel};
+use super::config::LinkConfig;
+use super::duplex::SelfInterference;
+use super::geometry::Motion;
+use super::mimo::MimoMatrix;
+use super::truth::LinkTruth;
+use super::tuning::{Translator, Tuning};
+use super::TxBlock;
+use num::{Complex, Zero};
+use rand::{distributions::Distribution, Rng};
+use rand_distr::Normal;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::f64::consts::PI;
+use std::ops::Range;
+
+/// The CFO random walk takes a step once every these many samples rather than every sample, which
+/// is much faster and barely changes its statistics
+const CFO_DRIFT_INTERVAL: usize = 64;
+
+/// Samples the Tx has sent that haven't been used yet
+struct TxStream {
+    /// Samples received from the Tx but not yet used
+    pending: VecDeque<Complex<f32>>,
+    /// Index of `pending[0]` in the Tx's timeline. If `pending` is empty, samples before this
+    /// index have already been used and the Tx was silent at those times
+    pending_start: u64,
+    /// The Tx's tuning from each index in its timeline onwards, in increasing order of index.
+    /// The first applies to the samples that are being used
+    tunings: VecDeque<(u64, Tuning)>,
+    /// Indices of the samples the Tx sent, as opposed to the gaps between its blocks when it was
+    /// silent, in increasing order. Contiguous blocks are merged into one range
+    sent: VecDeque<Range<u64>>,
+}
+
+/// Resamples the Tx's signal to simulate the Rx's sample clock running at a slightly different
+/// rate, or a changing delay. Uses cubic (4-point Lagrange) interpolation
+struct Resampler {
+    /// Position (in the Tx's timeline) of the next output sample, before `delay`
+    pos: f64,
+    /// Extra (fractional) delay of the next output sample, in Tx samples
+    delay: f64,
+    /// Tx samples around `pos`, starting at index `hist_start`. Samples before `pos` are forgotten
+    /// at the start of each block, since that means shifting the rest
+    hist: Vec<Complex<f32>>,
+    hist_start: u64,
+    /// Current offset (in ppm) of the Rx's sample clock relative to the Tx's
+    cur_sfo: f64,
+    /// Distance (in Tx samples) between consecutive output samples at the current offset
+    step: f64,
+    /// Change in `cur_sfo` every sample
+    sfo_drift: f64,
+}
+
+/// The part of a link that is separate for each of the Tx's antennas
+struct AntennaPath {
+    /// Samples the antenna has sent
+    stream: TxStream,
+    /// `None` if the sample clocks of the Tx and Rx are identical and the nodes don't move
+    resampler: Option<Resampler>,
+    /// Shifts and filters the antenna's signal to the Rx's tuning
+    translator: Translator,
+    /// Multipath channel between the antenna and the Rx
+    channel: MultipathChannel,
+    /// The antenna's signal in the last block, before the channel, CFO and gain were applied
+    clean: Vec<Complex<f32>>,
+}
+
+pub(super) struct Link {
+    /// One path for each of the Tx's antennas
+    paths: Vec<AntennaPath>,
+    /// The signal of each of the Tx's antennas as it passes through the link. Scratch space
+    signals: Vec<Vec<Complex<f32>>>,
+    /// Gains from the Tx's antennas to the Rx's
+    mimo: MimoMatrix,
+    /// The changing geometry of the link if the nodes move
+    motion: Option<Motion>,
+    /// Scratch space for the propagation delay (in samples) and gain due to `motion` at each
+    /// sample of a block
+    motion_delays: Vec<f64>,
+    motion_gains: Vec<Complex<f32>>,
+    /// Index of the Rx sample after the last block
+    rx_index: u64,
+    /// If true, the carrier is derived from the same oscillator as the sample clock, and is off by
+    /// the same number of ppm
+    cfo_from_sfo: bool,
+    /// Carrier frequency in Hz, i.e. the Rx's frequency
+    freq: f64,
+    /// Analog bandwidth (in Hz) of the Rx
+    bandwidth: f64,
+    /// Scratch space for the Tx's tuning from each sample of a block onwards
+    segments: Vec<(usize, Tuning)>,
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// Phase (in radians) of the CFO due to the sample clock offset
+    sfo_phase: f64,
+    /// Number of Rx samples before the Tx's first sample reaches the Rx
+    delay: u64,
+    /// Amplitude gain of the link
+    gain: f32,
+    /// `config.max_cfo` in radians/sample
+    max_cfo: f64,
+    /// Distribution of the steps of the CFO random walk in radians/sample, which are taken every
+    /// `CFO_DRIFT_INTERVAL` samples. `None` if the CFO is fixed
+    cfo_drift: Option<Normal<f64>>,
+    /// Distribution of the phase noise in radians/sample. `None` if there is no phase noise
+    phase_noise: Option<Normal<f64>>,
+    /// The current CFO in radians/sample (may drift as a random walk)
+    cur_cfo: f64,
+    /// Cumulative phase offset (in radians) so far due to CFO and phase noise (starts off with a
+    /// random phase)
+    phase: f64,
+    /// Samples left before the next step of the CFO random walk
+    until_drift: usize,
+}
+
+impl Link {
+    /// Create a link from a Tx with `tx_antennas` antennas to an Rx with `rx_antennas`, whose
+    /// random parameters (initial CFO and phase, fading) are drawn from `rng`. `config` must have
+    /// been validated, including for these numbers of antennas
+    pub fn new<R: Rng>(
+        config: &LinkConfig,
+        samp_rate: u64,
+        rx: Tuning,
+        tx_antennas: usize,
+        rx_antennas: usize,
+        rng: &mut R,
+    ) -> Self {
+        let freq = rx.freq;
+        let samp_rate_hz = samp_rate;
+        // Convert from Hz to radians/sample
+        let samp_rate = samp_rate as f64;
+        let max_cfo = 2. * PI * config.max_cfo / samp_rate;
+        let cfo_drift = 2. * PI * config.cfo_drift / samp_rate;
+        // The sum of the steps the random walk would take every sample
+        let cfo_drift = cfo_drift * (CFO_DRIFT_INTERVAL as f64).sqrt();
+
+        // Draw in a fixed order so that the same RNG state always gives the same link
+        let cur_cfo = 2. * rng.gen::<f64>() * max_cfo - max_cfo;
+        let phase = rng.gen::<f64>() * 2. * PI;
+        let motion = config
+            .mobility
+            .as_ref()
+            .map(|mobility| Motion::new(mobility, samp_rate_hz));
+        let resample = config.sfo != 0. || config.sfo_drift != 0. || motion.is_some();
+        let paths = (0..tx_antennas)
+            .map(|_| AntennaPath {
+                stream: TxStream::new(),
+                resampler: resample
+                    .then(|| Resampler::new(config.sfo, config.sfo_drift / samp_rate)),
+                translator: Translator::new(samp_rate_hz),
+                channel: MultipathChannel::new(&config.channel, samp_rate, freq, rng),
+                clean: Vec::new(),
+            })
+            .collect();
+        let mimo = MimoMatrix::new(&config.mimo, tx_antennas, rx_antennas, samp_rate_hz, rng);
+
+        // Validated, so these are non-negative
+        let normal = |std: f64| (std > 0.).then(|| Normal::new(0., std).unwrap());
+        Self {
+            paths,
+            signals: vec![Vec::new(); tx_antennas],
+            mimo,
+            motion,
+            motion_delays: Vec::new(),
+            motion_gains: Vec::new(),
+            rx_index: 0,
+            cfo_from_sfo: config.cfo_from_sfo,
+            freq,
+            bandwidth: rx.bandwidth,
+            segments: Vec::new(),
+            samp_rate,
+            sfo_phase: 0.,
+            delay: config.delay,
+            gain: 10f32.powf(config.gain as f32 / 20.),
+            max_cfo,
+            cfo_drift: normal(cfo_drift),
+            phase_noise: normal(config.phase_noise),
+            cur_cfo,
+            phase,
+            until_drift: 0,
+        }
+    }
+
+    /// The leakage from a Tx into the Rx of the same device, which share an LO and a sample clock,
+    /// so its gains are just those of `config`'s taps. `config` must have been validated
+    pub fn self_interference<R: Rng>(
+        config: &SelfInterference,
+        samp_rate: u64,
+        rx: Tuning,
+        tx_antennas: usize,
+        rx_antennas: usize,
+        rng: &mut R,
+    ) -> Self {
+        let mut link = Self::new(
+            &LinkConfig::default(),
+            samp_rate,
+            rx,
+            tx_antennas,
+            rx_antennas,
+            rng,
+        );
+        link.phase = 0.;
+        let taps = config.taps();
+        for path in &mut link.paths {
+            path.channel = MultipathChannel::from_taps(&taps, samp_rate as f64, rx.freq);
+        }
+        link
+    }
+
+    /// Set the gain (in dB) of the link. It applies from the next block onwards
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain = 10f32.powf(gain as f32 / 20.);
+    }
+
+    /// Replace the multipath channel of every antenna. Random gains are drawn from `rng`. `model`
+    /// must have been validated
+    pub fn set_channel<R: Rng>(&mut self, model: &ChannelModel, rng: &mut R) {
+        for path in &mut self.paths {
+            path.channel = MultipathChannel::new(model, self.samp_rate, self.freq, rng);
+        }
+    }
+
+    /// Add a block sent by the Tx. Any part of it that should already have reached the Rx is
+    /// dropped, since the Rx has moved on
+    pub fn push(&mut self, block: &TxBlock) {
+        for (antenna, path) in self.paths.iter_mut().enumerate() {
+            path.stream.push(block, antenna);
+        }
+    }
+
+    /// The Rx retuned
+    pub fn set_tuning(&mut self, rx: Tuning) {
+        self.freq = rx.freq;
+        self.bandwidth = rx.bandwidth;
+        for path in &mut self.paths {
+            path.channel.set_freq(rx.freq);
+        }
+    }
+
+    /// The Rx dropped samples, and will continue from its `rx_index`^th sample. Samples the Tx
+    /// sent before then are dropped
+    pub fn skip_to(&mut self, rx_index: u64) {
+        self.rx_index = rx_index;
+        let tx_index = rx_index.saturating_sub(self.delay);
+        for path in &mut self.paths {
+            path.stream.discard_before(tx_index);
+            if let Some(resampler) = &mut path.resampler {
+                resampler.restart(tx_index);
+            }
+        }
+    }
+
+    /// Add the signal from this link in the Rx's samples starting at `rx_index` to `out`, which
+    /// has one buffer for each of the Rx's antennas. Must be called for consecutive blocks, except
+    /// after `skip_to`
+    pub fn add_block<R: Rng>(&mut self, rx_index: u64, out: &mut [Vec<Complex<f32>>], rng: &mut R) {
+        let len = out[0].len();
+        self.rx_index = rx_index + len as u64;
+        for (path, signal) in self.paths.iter_mut().zip(&mut self.signals) {
+            path.clean.clear();
+            path.clean.resize(len, Complex::zero());
+            signal.clear();
+        }
+
+        // Nothing reaches the Rx before the delay
+        let silent = (self.delay.saturating_sub(rx_index) as usize).min(len);
+        let tx_index = (rx_index + silent as u64).saturating_sub(self.delay);
+        if self.paths[0].resampler.is_some() {
+            self.motion_delays.clear();
+            self.motion_gains.clear();
+            if let Some(motion) = &mut self.motion {
+                motion.block(
+                    rx_index + silent as u64,
+                    len - silent,
+                    self.freq,
+                    &mut self.motion_delays,
+                    &mut self.motion_gains,
+                );
+            }
+            let resampler = self.paths[0].resampler.as_ref().unwrap();
+            let (mut cur_sfo, sfo_drift) = (resampler.cur_sfo, resampler.sfo_drift);
+            // The antennas share the Rx's sample clock, so their resamplers move in lockstep
+            for (path, signal) in self.paths.iter_mut().zip(&mut self.signals) {
+                let resampler = path.resampler.as_mut().unwrap();
+                let clean = &mut path.clean[silent..];
+                resampler.process_block(tx_index, &self.motion_delays, &mut path.stream, clean);
+                signal.extend_from_slice(&path.clean);
+                for (samp, gain) in signal[silent..].iter_mut().zip(&self.motion_gains) {
+                    *samp *= gain;
+                }
+            }
+            if self.cfo_from_sfo {
+                let two_pi = 2. * PI;
+                for i in silent..len {
+                    // Like the resamplers, update the offset on moving past each sample
+                    cur_sfo += sfo_drift;
+                    // A faster Rx oscillator shifts the signal down in frequency
+                    self.sfo_phase -= two_pi * self.freq * cur_sfo * 1e-6 / self.samp_rate;
+                    self.sfo_phase %= two_pi;
+                    let rotation = Complex::from_polar(&1., &(self.sfo_phase as f32));
+                    for signal in &mut self.signals {
+                        signal[i] *= rotation;
+                    }
+                }
+            }
+        } else {
+            for (path, signal) in self.paths.iter_mut().zip(&mut self.signals) {
+                path.stream.take_block(tx_index, &mut path.clean[silent..]);
+                signal.extend_from_slice(&path.clean);
+            }
+        }
+
+        // Translate from the Tx's tuning to the Rx's. The antennas share the Tx's tuning
+        self.segments.clear();
+        self.segments.push((0, self.rx_tuning()));
+        for (start, tuning) in &self.paths[0].stream.tunings {
+            let start = (silent as u64 + start.saturating_sub(tx_index)) as usize;
+            if start >= len {
+                break;
+            }
+            // Tunings before the start of the block apply from its start
+            if self.segments.last().unwrap().0 == start {
+                self.segments.pop();
+            }
+            self.segments.push((start, *tuning));
+        }
+        let rx = self.rx_tuning();
+        for (path, signal) in self.paths.iter_mut().zip(&mut self.signals) {
+            path.translator
+                .process(signal, rx_index, &self.segments, rx);
+            path.stream
+                .forget_tunings_before(tx_index + (len - silent) as u64);
+
+            // Include multipath effects
+            path.channel.process_block(signal);
+        }
+
+        // CFO, phase noise and gain, which the antennas share. Rotate a phasor in double precision,
+        // which stays accurate over a block. Without MIMO gains to apply, the signals go straight
+        // to the Rx's antennas
+        let direct = self.mimo.is_identity();
+        let mut phasor = Complex::from_polar(&1., &self.phase);
+        let mut rotations = [Complex::zero(); CFO_DRIFT_INTERVAL];
+        let mut pos = 0;
+        while pos < len {
+            // The CFO is constant till its next step
+            if self.until_drift == 0 {
+                if let Some(cfo_drift) = &self.cfo_drift {
+                    let drift = cfo_drift.sample(rng);
+                    self.cur_cfo = (self.cur_cfo + drift).clamp(-self.max_cfo, self.max_cfo);
+                }
+                self.until_drift = CFO_DRIFT_INTERVAL;
+            }
+            let chunk = self.until_drift.min(len - pos);
+            let rotations = &mut rotations[..chunk];
+            match &self.phase_noise {
+                Some(phase_noise) => {
+                    for rotation in rotations.iter_mut() {
+                        phasor *= unit_phasor(self.cur_cfo + phase_noise.sample(rng));
+                        *rotation = Complex::new(phasor.re as f32, phasor.im as f32) * self.gain;
+                    }
+                }
+                None => {
+                    let step = Complex::from_polar(&1., &self.cur_cfo);
+                    for rotation in rotations.iter_mut() {
+                        phasor *= step;
+                        *rotation = Complex::new(phasor.re as f32, phasor.im as f32) * self.gain;
+                    }
+                }
+            }
+            for (antenna, signal) in self.signals.iter_mut().enumerate() {
+                let signal = signal[pos..pos + chunk].iter_mut().zip(rotations.iter());
+                match out.get_mut(antenna) {
+                    Some(out) if direct => {
+                        for (o, (samp, rotation)) in out[pos..pos + chunk].iter_mut().zip(signal) {
+                            *o += *samp * rotation;
+                        }
+                    }
+                    _ => signal.for_each(|(samp, rotation)| *samp *= rotation),
+                }
+            }
+            self.until_drift -= chunk;
+            pos += chunk;
+        }
+        self.phase = phasor.arg();
+
+        // From the Tx's antennas to the Rx's
+        if !direct {
+            self.mimo.mix(&self.signals, out);
+        }
+    }
+
+    fn rx_tuning(&self) -> Tuning {
+        Tuning {
+            freq: self.freq,
+            bandwidth: self.bandwidth,
+        }
+    }
+
+    /// The signal of the Tx's first antenna in the samples of the last call to `add_block`,
+    /// aligned to the Rx's timeline (and resampled to its sample clock) but before the channel,
+    /// CFO and gain
+    pub fn clean(&self) -> &[Complex<f32>] {
+        &self.paths[0].clean
+    }
+
+    /// Snapshot of the link's state, for an Rx with the given noise power (in dB)
+    pub fn truth(&self, noise_power: f64) -> LinkTruth {
+        let first = &self.paths[0];
+        let (sfo, sfo_drift) = first
+            .resampler
+            .as_ref()
+            .map_or((0., 0.), |r| (r.cur_sfo, r.sfo_drift));
+        let mut cfo = self.cur_cfo * self.samp_rate / (2. * PI);
+        // `phase` is that of the last sample, and the phase moves on before each sample is rotated
+        let mut phase = self.phase + self.cur_cfo;
+        if self.cfo_from_sfo {
+            cfo -= self.freq * sfo * 1e-6;
+            let step = 2. * PI * self.freq * (sfo + sfo_drift) * 1e-6 / self.samp_rate;
+            phase += self.sfo_phase - step;
+        }
+        // The filter that selects the part of the Tx's band the Rx hears delays the signal
+        let (filter_delay, shift) = first.translator.offsets();
+        cfo += shift;
+        let mut delay = self.delay + filter_delay;
+        let mut gain = 20. * (self.gain as f64).log10();
+        if let Some(motion) = &self.motion {
+            let (motion_gain, motion_delay, doppler) = motion.truth(self.rx_index, self.freq);
+            delay += (motion_delay * self.samp_rate).round() as u64;
+            gain += motion_gain;
+            cfo += doppler;
+            phase -= 2. * PI * self.freq * motion_delay;
+        }
+        let taps = first.channel.taps();
+        let channel_power: f32 = taps.iter().map(|(_, g)| g.norm_sqr()).sum();
+        LinkTruth {
+            delay,
+            cfo,
+            // Wrap to (-pi, pi]
+            phase: Complex::from_polar(&1., &phase).arg(),
+            sfo,
+            snr: gain + 10. * (channel_power as f64).log10() - noise_power,
+            taps,
+            mimo: self.mimo.gains(),
+        }
+    }
+}
+
+/// `e^(j * angle)`. Small angles, such as the phase change over a sample, are common and computed
+/// with a Taylor series, which is faster than `Complex::from_polar`
+fn unit_phasor(angle: f64) -> Complex<f64> {
+    if angle.abs() < 0.1 {
+        // The error is less than angle^6 / 720 < 2e-9
+        let a2 = angle * angle;
+        Complex::new(
+            1. - a2 / 2. * (1. - a2 / 12.),
+            angle * (1. - a2 / 6. * (1. - a2 / 20.)),
+        )
+    } else {
+        Complex::from_polar(&1., &angle)
+    }
+}
+
+impl TxStream {
+    fn new() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            pending_start: 0,
+            tunings: VecDeque::new(),
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Add the samples of the Tx's `antenna`^th antenna in `block`
+    fn push(&mut self, block: &TxBlock, antenna: usize) {
+        if self.tunings.back().map(|(_, t)| *t) != Some(block.tuning) {
+            self.tunings.push_back((block.start, block.tuning));
+        }
+        let pending_end = self.pending_start + self.pending.len() as u64;
+        if self.pending.is_empty() && block.start > self.pending_start {
+            self.pending_start = block.start;
+        } else {
+            // The Tx was silent between the samples we have and the start of this block
+            for _ in pending_end..block.start {
+                self.pending.push_back(Complex::zero());
+            }
+        }
+        let skip = pending_end.saturating_sub(block.start) as usize;
+        self.pending
+            .extend(block.samples[antenna].iter().skip(skip));
+        let sent = block.start + skip as u64..block.start + block.samples[antenna].len() as u64;
+        if !sent.is_empty() {
+            match self.sent.back_mut() {
+                Some(last) if last.end == sent.start => last.end = sent.end,
+                _ => self.sent.push_back(sent),
+            }
+        }
+    }
+
+    /// Take the Tx's `index`^th sample, where `now` is the index of the current time in the Tx's
+    /// timeline. Indices must be increasing, and any samples before `index` are discarded. Returns
+    /// `None` if `index` is in the future and the Tx hasn't decided what to send then yet
+    fn take(&mut self, index: u64, now: u64) -> Option<Complex<f32>> {
+        self.discard_before(index);
+        if self.pending.is_empty() {
+            if index > now {
+                return None;
+            }
+            // Silent. If the Tx sends this sample later, it will be too late
+            self.pending_start = index + 1;
+            Some(Complex::zero())
+        } else if self.pending_start == index {
+            self.pending_start += 1;
+            self.pending.pop_front()
+        } else {
+            // Silent until the next block starts
+            debug_assert!(self.pending_start > index);
+            Some(Complex::zero())
+        }
+    }
+
+    /// Take the Tx's samples starting at `first` into `out`. Like `take`, but the samples must
+    /// all be in the past, so that any the Tx hasn't sent are silent
+    fn take_block(&mut self, first: u64, out: &mut [Complex<f32>]) {
+        self.discard_before(first);
+        self.forget_sent_before(first);
+        let end = first + out.len() as u64;
+        // Silent until the next block starts
+        let lead = (self.pending_start.min(end) - first) as usize;
+        let avail = self.pending.len().min(out.len() - lead);
+        for samp in &mut out[..lead] {
+            *samp = Complex::zero();
+        }
+        for (samp, sent) in out[lead..].iter_mut().zip(self.pending.drain(..avail)) {
+            *samp = sent;
+        }
+        for samp in &mut out[lead + avail..] {
+            *samp = Complex::zero();
+        }
+        self.pending_start += avail as u64;
+        if self.pending.is_empty() {
+            // If the Tx sends these samples later, it will be too late
+            self.pending_start = self.pending_start.max(end);
+        }
+    }
+
+    /// Append the Tx's samples from `first` up to `end` to `out`, as far as the Tx has sent them.
+    /// Like calling `take` for each of them, which are all known whatever the current time
+    fn take_sent(&mut self, first: u64, end: u64, out: &mut Vec<Complex<f32>>) {
+        self.discard_before(first);
+        if self.pending.is_empty() {
+            return;
+        }
+        let end = end.min(self.pending_start + self.pending.len() as u64);
+        if end <= first {
+            return;
+        }
+        // Silent until the next block starts
+        let lead = (self.pending_start.min(end) - first) as usize;
+        out.resize(out.len() + lead, Complex::zero());
+        let avail = (end - first) as usize - lead;
+        let (front, back) = self.pending.as_slices();
+        let from_front = avail.min(front.len());
+        out.extend_from_slice(&front[..from_front]);
+        out.extend_from_slice(&back[..avail - from_front]);
+        self.pending.drain(..avail);
+        self.pending_start += avail as u64;
+    }
+
+    /// Drop samples before `index`, which are too late to be used
+    fn discard_before(&mut self, index: u64) {
+        while self.pending_start < index && !self.pending.is_empty() {
+            self.pending.pop_front();
+            self.pending_start += 1;
+        }
+        if self.pending.is_empty() {
+            self.pending_start = self.pending_start.max(index);
+        }
+    }
+
+    /// The first range of samples the Tx sent that overlaps `first..=last`, if it sent any of them
+    fn sent_run(&self, first: u64, last: u64) -> Option<Range<u64>> {
+        self.sent
+            .iter()
+            .find(|sent| sent.end > first)
+            .filter(|sent| sent.start <= last)
+            .cloned()
+    }
+
+    /// Forget which samples before `index` the Tx sent
+    fn forget_sent_before(&mut self, index: u64) {
+        while self.sent.front().is_some_and(|sent| sent.end <= index) {
+            self.sent.pop_front();
+        }
+    }
+
+    /// Drop the tunings that no longer apply at `index`
+    fn forget_tunings_before(&mut self, index: u64) {
+        while self
+            .tunings
+            .get(1)
+            .is_some_and(|(start, _)| *start <= index)
+        {
+            self.tunings.pop_front();
+        }
+    }
+}
+
+impl Resampler {
+    /// `sfo` is in ppm and `sfo_drift` in ppm/sample
+    fn new(sfo: f64, sfo_drift: f64) -> Self {
+        Self {
+            pos: 0.,
+            delay: 0.,
+            hist: Vec::new(),
+            hist_start: 0,
+            cur_sfo: sfo,
+            step: 1. / (1. + sfo * 1e-6),
+            sfo_drift,
+        }
+    }
+
+    /// Continue from Tx sample `index`, forgetting earlier samples
+    fn restart(&mut self, index: u64) {
+        self.pos = index as f64;
+        self.hist.clear();
+        self.hist_start = index.saturating_sub(1);
+    }
+
+    /// Fill `out` with the next output samples, taking the Tx's samples from `stream`. `now` is the
+    /// index of the current time in the Tx's timeline at the first of them, and `delays` is either
+    /// empty or has the extra delay (in Tx samples) of each of them
+    fn process_block(
+        &mut self,
+        now: u64,
+        delays: &[f64],
+        stream: &mut TxStream,
+        out: &mut [Complex<f32>],
+    ) {
+        if let Some(delay) = delays.first() {
+            self.delay = *delay;
+        }
+        let stale = self.num_stale();
+        self.hist.drain(..stale);
+        self.hist_start += stale as u64;
+        stream.forget_sent_before(self.first());
+        if self.hist.is_empty() {
+            self.hist_start = self.hist_start.max(self.first());
+        }
+        // Take the samples the Tx has already sent for this block in one go. The position moves on
+        // by about one Tx sample per output sample
+        let next = self.hist_start + self.hist.len() as u64;
+        let end = (self.pos - self.delay).max(0.) as u64 + out.len() as u64 + 3;
+        stream.take_sent(next, end, &mut self.hist);
+
+        let mut i = 0;
+        while i < out.len() {
+            // Usually all the samples needed are at hand
+            let fast = self.fast_range(stream);
+            i += self.interpolate_run(delays.get(i..).unwrap_or(&[]), &mut out[i..], fast);
+            if i == out.len() {
+                break;
+            }
+
+            // Otherwise the sample may start a new burst, or need samples the Tx hasn't sent yet
+            if let Some(delay) = delays.get(i) {
+                self.delay = *delay;
+            }
+            out[i] = self.next(now + i as u64, stream);
+            i += 1;
+        }
+    }
+
+    /// Return the next output sample, where `now` is the index of the current time in the Tx's
+    /// timeline
+    fn next(&mut self, now: u64, stream: &mut TxStream) -> Complex<f32> {
+        // When the Tx is silent (it sent none of the samples the next output sample needs, as
+        // opposed to sending zeros), there is nothing to stretch. Start afresh from the current time
+        // so that the sample clock offset doesn't shift later bursts by an ever increasing amount.
+        // This deliberately drops the drift so far, which would otherwise make bursts sent without
+        // a time miss the samples they were sent for
+        if self.is_silent(stream) {
+            self.pos = now as f64;
+        }
+        let pos = self.pos - self.delay;
+        if pos < 0. {
+            // Before the start of the Tx's timeline
+            self.advance();
+            return Complex::zero();
+        }
+
+        // We need the samples at base - 1, ..., base + 2
+        // `pos` is not negative, so truncating is the same as (but much faster than) `floor`
+        let base = pos as u64;
+        if self.hist.is_empty() {
+            self.hist_start = self.hist_start.max(base.saturating_sub(1));
+        }
+        loop {
+            let next = self.hist_start + self.hist.len() as u64;
+            if next > base + 2 {
+                break;
+            }
+            match stream.take(next, now) {
+                Some(samp) => self.hist.push(samp),
+                // Not known yet. Treat it as silent for now
+                None => break,
+            }
+        }
+        let samp = self.interpolate_partial(pos, base);
+        self.advance();
+        samp
+    }
+
+    /// Interpolate output samples for as long as their positions (before `delay`) are in `fast`.
+    /// `delays` is as for `process_block`. Returns the number of samples
+    fn interpolate_run(
+        &mut self,
+        delays: &[f64],
+        out: &mut [Complex<f32>],
+        fast: Range<f64>,
+    ) -> usize {
+        // Keep the position in a local, which is faster
+        let mut next = self.pos;
+        let mut len = out.len();
+        for (i, samp) in out.iter_mut().enumerate() {
+            if let Some(delay) = delays.get(i) {
+                self.delay = *delay;
+            }
+            let pos = next - self.delay;
+            if !fast.contains(&pos) {
+                len = i;
+                break;
+            }
+            let base = pos as u64;
+            let offset = (base - 1 - self.hist_start) as usize;
+            let samps: &[_; 4] = self.hist[offset..offset + 4].try_into().unwrap();
+            *samp = interpolate(pos - base as f64, samps);
+            next = self.next_pos(next);
+        }
+        self.pos = next;
+        len
+    }
+
+    /// Positions (before `delay`) at which all four samples are in `hist`, and which are in the
+    /// same run of samples the Tx sent as the next output sample. Empty if the Tx is silent at
+    /// the next output sample
+    fn fast_range(&self, stream: &TxStream) -> Range<f64> {
+        let hist_end = self.hist_start + self.hist.len() as u64;
+        let (first, last) = self.window();
+        match stream.sent_run(first, last) {
+            // The samples a position needs overlap the run between 2 samples before its start
+            // and 1 after its end
+            Some(run) => {
+                let start = (self.hist_start + 1).max(run.start.saturating_sub(2));
+                let end = hist_end.saturating_sub(2).min(run.end + 1);
+                start as f64..end as f64
+            }
+            None => 0. ..0.,
+        }
+    }
+
+    /// Whether the Tx sent none of the samples the next output sample needs
+    fn is_silent(&self, stream: &TxStream) -> bool {
+        let (first, last) = self.window();
+        stream.sent_run(first, last).is_none()
+    }
+
+    /// Indices of the first and last Tx samples the next output sample needs
+    fn window(&self) -> (u64, u64) {
+        // Negative positions saturate to 0
+        let base = (self.pos - self.delay) as u64;
+        (base.saturating_sub(1), base + 2)
+    }
+
+    /// Interpolate the Tx's signal at `pos`, whose integer part is `base`, from the samples around
+    /// it that are known. The others are taken to be silent
+    fn interpolate_partial(&self, pos: f64, base: u64) -> Complex<f32> {
+        let coeffs = lagrange_coeffs(pos - base as f64);
+        let mut samp = Complex::zero();
+        for (i, coeff) in coeffs.iter().enumerate() {
+            // Index base - 1 + i, which may be before the start of the Tx's timeline
+            let index = (base + i as u64).checked_sub(1);
+            if let Some(index) = index {
+                if index >= self.hist_start {
+                    if let Some(s) = self.hist.get((index - self.hist_start) as usize) {
+                        samp += s * coeff;
+                    }
+                }
+            }
+        }
+        samp
+    }
+
+    /// Index of the first Tx sample the next output sample needs
+    fn first(&self) -> u64 {
+        self.window().0
+    }
+
+    /// Number of samples at the start of `hist` that are no longer needed
+    fn num_stale(&self) -> usize {
+        (self.first().saturating_sub(self.hist_start) as usize).min(self.hist.len())
+    }
+
+    /// Move on to the next output sample
+    fn advance(&mut self) {
+        self.pos = self.next_pos(self.pos);
+    }
+
+    /// Position of the output sample after the one at `pos`. The sample clock offset drifts as
+    /// the Rx moves on
+    fn next_pos(&mut self, pos: f64) -> f64 {
+        // A faster Rx clock takes more samples of the same signal
+        let next = pos + self.step;
+        if self.sfo_drift != 0. {
+            self.cur_sfo += self.sfo_drift;
+            self.step = 1. / (1. + self.cur_sfo * 1e-6);
+        }
+        next
+    }
+}
+
+/// Coefficients of 4-point Lagrange interpolation at `mu` (between 0 and 1) samples after the
+/// second point
+fn lagrange_coeffs(mu: f64) -> [f32; 4] {
+    let mu = mu as f32;
+    [
+        -mu * (mu - 1.) * (mu - 2.) / 6.,
+        (mu + 1.) * (mu - 1.) * (mu - 2.) / 2.,
+        -(mu + 1.) * mu * (mu - 2.) / 2.,
+        (mu + 1.) * mu * (mu - 1.) / 6.,
+    ]
+}
+
+/// Interpolate between `samps` at `mu` (between 0 and 1) samples after the second
+fn interpolate(mu: f64, samps: &[Complex<f32>; 4]) -> Complex<f32> {
+    let coeffs = lagrange_coeffs(mu);
+    samps[0] * coeffs[0] + samps[1] * coeffs[1] + samps[2] * coeffs[2] + samps[3] * coeffs[3]
+}
@@ -0,0 +1,436 @@
+//! Simulated radios with several antennas, each with its own channel. The Tx's antennas reach the
+//! Rx's antennas through the gains of a `MimoChannel`, on top of the rest of the link (delay, CFO,
+//! multipath and so on), which every pair of antennas shares. Each Rx antenna has its own noise,
+//! and the antennas of a radio may have unsynchronized LOs, which offset their phases.
+
+use super::channel::{fading_update_interval, Scatter};
+use crate::RadioError;
+use num::{Complex, Zero};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// The antennas of a simulated radio, each of which is a channel of `RadioTxMulti` or
+/// `RadioRxMulti`
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Antennas {
+    /// Number of antennas
+    pub count: usize,
+    /// Phase offset (in radians) of each antenna's LO. Empty if the antennas share an LO, so
+    /// that there are no offsets
+    pub lo_phases: Vec<f64>,
+    /// Noise power (in dB) of each antenna relative to the Rx's noise power, e.g. due to
+    /// differences in noise figure. Empty if they are all the same. Ignored for a Tx
+    pub noise_offsets: Vec<f64>,
+}
+
+impl Default for Antennas {
+    /// A single antenna
+    fn default() -> Self {
+        Self {
+            count: 1,
+            lo_phases: Vec::new(),
+            noise_offsets: Vec::new(),
+        }
+    }
+}
+
+impl Antennas {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if self.count == 0 {
+            return invalid("a radio must have at least one antenna");
+        }
+        for (name, values) in [
+            ("lo_phases", &self.lo_phases),
+            ("noise_offsets", &self.noise_offsets),
+        ] {
+            if !(values.is_empty() || values.len() == self.count) {
+                return Err(RadioError::InvalidConfig(format!(
+                    "{} must be empty or have one entry per antenna",
+                    name
+                )));
+            }
+            if !values.iter().all(|x| x.is_finite()) {
+                return Err(RadioError::InvalidConfig(format!(
+                    "{} must be finite",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The phase offset of each antenna's LO, as a unit phasor
+    pub(super) fn lo_phasors(&self) -> Vec<Complex<f32>> {
+        (0..self.count)
+            .map(|i| {
+                let phase = self.lo_phases.get(i).copied().unwrap_or(0.);
+                Complex::from_polar(&1., &(phase as f32))
+            })
+            .collect()
+    }
+
+    /// Noise power (in dB) of each antenna, for an Rx whose noise power is `noise_power`
+    pub(super) fn noise_powers(&self, noise_power: f64) -> Vec<f64> {
+        (0..self.count)
+            .map(|i| noise_power + self.noise_offsets.get(i).copied().unwrap_or(0.))
+            .collect()
+    }
+}
+
+/// Gains from every Tx antenna to every Rx antenna of a link
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub enum MimoChannel {
+    /// Each Rx antenna hears only the Tx antenna with the same index (if any), with unit gain.
+    /// With one antenna at either end, this is an ordinary single antenna link
+    #[default]
+    Identity,
+    /// Fixed gains, where `gains[i][j]` is the gain from Tx antenna `j` to Rx antenna `i`
+    Matrix(Vec<Vec<Complex<f32>>>),
+    /// Every gain is Rayleigh distributed with unit average power. The gains are correlated
+    /// following the Kronecker model with exponential correlation: the correlation between Tx
+    /// antennas `j` and `k` is `tx_correlation^|j - k|`, and likewise at the Rx. Each gain fades
+    /// over time with a Jakes Doppler spectrum whose maximum Doppler shift is `max_doppler` Hz.
+    /// If that is 0, the gains are random but fixed
+    Rayleigh {
+        tx_correlation: f64,
+        rx_correlation: f64,
+        max_doppler: f64,
+    },
+}
+
+impl MimoChannel {
+    /// Check that the parameters make sense for radios with the given sample rate
+    pub(super) fn validate(&self, samp_rate: u64) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        match self {
+            MimoChannel::Identity => {}
+            MimoChannel::Matrix(gains) => {
+                if gains.is_empty() || gains[0].is_empty() {
+                    return invalid("the MIMO matrix must have at least one row and column");
+                }
+                if gains.iter().any(|row| row.len() != gains[0].len()) {
+                    return invalid("every row of the MIMO matrix must have the same length");
+                }
+                if !gains
+                    .iter()
+                    .flatten()
+                    .all(|g| g.re.is_finite() && g.im.is_finite())
+                {
+                    return invalid("MIMO gains must be finite");
+                }
+            }
+            MimoChannel::Rayleigh {
+                tx_correlation,
+                rx_correlation,
+                max_doppler,
+            } => {
+                let correlation = |c: f64| (0. ..1.).contains(&c);
+                if !(correlation(*tx_correlation) && correlation(*rx_correlation)) {
+                    return invalid("MIMO correlations must be in [0, 1)");
+                }
+                if !(*max_doppler >= 0. && *max_doppler < samp_rate as f64 / 2.) {
+                    return invalid("max_doppler must be non-negative and less than samp_rate / 2");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the channel fits a link between radios with the given numbers of antennas
+    pub(super) fn validate_antennas(
+        &self,
+        tx_antennas: usize,
+        rx_antennas: usize,
+    ) -> Result<(), RadioError> {
+        match self {
+            MimoChannel::Matrix(gains)
+                if gains.len() != rx_antennas || gains[0].len() != tx_antennas =>
+            {
+                Err(RadioError::InvalidConfig(format!(
+                    "the MIMO matrix must have {} rows (Rx antennas) and {} columns (Tx antennas)",
+                    rx_antennas, tx_antennas
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Applies a `MimoChannel`, mixing the signals of the Tx's antennas into the Rx's antennas
+pub(super) struct MimoMatrix {
+    tx_antennas: usize,
+    /// Current gain from Tx antenna `j` to Rx antenna `i`, at `i * tx_antennas + j`
+    gains: Vec<Complex<f32>>,
+    /// If true, the gains are those of `MimoChannel::Identity`, and Rx antenna `i` hears just Tx
+    /// antenna `i`
+    identity: bool,
+    /// The fading processes, if the gains fade
+    fading: Option<MimoFading>,
+}
+
+/// Gains of a `MimoChannel::Rayleigh`
+struct MimoFading {
+    /// Uncorrelated fading process of each gain, in the same order as `MimoMatrix::gains`
+    scatter: Vec<Scatter>,
+    /// Lower triangular square roots of the Rx's and the Tx's correlation matrices
+    rx_root: Vec<Vec<f64>>,
+    tx_root: Vec<Vec<f64>>,
+    /// Number of samples between updates of the gains, and the samples left till the next one.
+    /// `None` if the gains don't change
+    update_interval: Option<usize>,
+    until_update: usize,
+}
+
+impl MimoMatrix {
+    /// Random gains are drawn from `rng`. `channel` must have been validated, including for these
+    /// numbers of antennas
+    pub fn new<R: Rng>(
+        channel: &MimoChannel,
+        tx_antennas: usize,
+        rx_antennas: usize,
+        samp_rate: u64,
+        rng: &mut R,
+    ) -> Self {
+        let mut res = Self {
+            tx_antennas,
+            gains: vec![Complex::zero(); tx_antennas * rx_antennas],
+            identity: false,
+            fading: None,
+        };
+        match channel {
+            MimoChannel::Identity => {
+                for i in 0..rx_antennas.min(tx_antennas) {
+                    res.gains[i * tx_antennas + i] = Complex::new(1., 0.);
+                }
+                res.identity = true;
+            }
+            MimoChannel::Matrix(gains) => {
+                res.gains = gains.iter().flatten().copied().collect();
+            }
+            MimoChannel::Rayleigh {
+                tx_correlation,
+                rx_correlation,
+                max_doppler,
+            } => {
+                // Maximum doppler in cycles/sample
+                let doppler = max_doppler / samp_rate as f64;
+                let interval = fading_update_interval(doppler);
+                let update_interval = (*max_doppler > 0.).then_some(interval);
+                let fading = MimoFading {
+                    scatter: (0..tx_antennas * rx_antennas)
+                        .map(|_| Scatter::new(doppler, interval, rng))
+                        .collect(),
+                    rx_root: correlation_root(*rx_correlation, rx_antennas),
+                    tx_root: correlation_root(*tx_correlation, tx_antennas),
+                    update_interval,
+                    until_update: interval,
+                };
+                fading.correlate(tx_antennas, &mut res.gains);
+                res.fading = Some(fading);
+            }
+        }
+        res
+    }
+
+    /// The current gains, where `[i][j]` is the gain from Tx antenna `j` to Rx antenna `i`
+    pub fn gains(&self) -> Vec<Vec<Complex<f32>>> {
+        self.gains
+            .chunks(self.tx_antennas)
+            .map(|row| row.to_vec())
+            .collect()
+    }
+
+    /// Whether Rx antenna `i` just hears Tx antenna `i`, so the signals need no mixing
+    pub fn is_identity(&self) -> bool {
+        self.identity
+    }
+
+    /// Add the signals of the Tx's antennas, `inputs`, to those of the Rx's antennas, `outputs`,
+    /// through the current gains. All the signals must have the same length
+    pub fn mix(&mut self, inputs: &[Vec<Complex<f32>>], outputs: &mut [Vec<Complex<f32>>]) {
+        let len = inputs[0].len();
+        let mut pos = 0;
+        while pos < len {
+            let chunk = self.next_chunk(len - pos);
+            for (row, output) in self.gains.chunks(self.tx_antennas).zip(outputs.iter_mut()) {
+                for (gain, input) in row.iter().zip(inputs) {
+                    if gain.is_zero() {
+                        continue;
+                    }
+                    let output = &mut output[pos..pos + chunk];
+                    for (o, x) in output.iter_mut().zip(&input[pos..pos + chunk]) {
+                        *o += gain * x;
+                    }
+                }
+            }
+            pos += chunk;
+        }
+    }
+
+    /// Number of samples, up to `max`, for which the gains stay as they are. Fading gains that are
+    /// due are updated first
+    fn next_chunk(&mut self, max: usize) -> usize {
+        let fading = match &mut self.fading {
+            Some(fading) => fading,
+            None => return max,
+        };
+        let interval = match fading.update_interval {
+            Some(interval) => interval,
+            None => return max,
+        };
+        if fading.until_update == 0 {
+            fading.scatter.iter_mut().for_each(Scatter::advance);
+            fading.correlate(self.tx_antennas, &mut self.gains);
+            fading.until_update = interval;
+        }
+        let chunk = fading.until_update.min(max);
+        fading.until_update -= chunk;
+        chunk
+    }
+}
+
+impl MimoFading {
+    /// Set `gains` (in the order of `MimoMatrix::gains`) to the current values of the fading
+    /// processes, correlated as `rx_root * uncorrelated * tx_root^T`
+    fn correlate(&self, tx_antennas: usize, gains: &mut [Complex<f32>]) {
+        let uncorrelated: Vec<Complex<f64>> = self.scatter.iter().map(Scatter::gain).collect();
+        for (i, rx_root) in self.rx_root.iter().enumerate() {
+            for (j, tx_root) in self.tx_root.iter().enumerate() {
+                let mut gain = Complex::<f64>::zero();
+                // The roots are lower triangular
+                for (k, rx_coeff) in rx_root[..=i].iter().enumerate() {
+                    for (l, tx_coeff) in tx_root[..=j].iter().enumerate() {
+                        gain += uncorrelated[k * tx_antennas + l] * rx_coeff * tx_coeff;
+                    }
+                }
+                gains[i * tx_antennas + j] = Complex::new(gain.re as f32, gain.im as f32);
+            }
+        }
+    }
+}
+
+/// The lower triangular square root `L` of the exponential correlation matrix `R` of `n`
+/// antennas, where `R[i][j] = rho^|i - j|` and `R = L * L^T`
+fn correlation_root(rho: f64, n: usize) -> Vec<Vec<f64>> {
+    let scale = (1. - rho * rho).sqrt();
+    (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| match j {
+                    0 => rho.powi(i as i32),
+                    j if j <= i => rho.powi((i - j) as i32) * scale,
+                    _ => 0.,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    const SAMP_RATE: u64 = 1_000_000;
+
+    /// Mix a constant signal from each Tx antenna through `matrix`
+    fn mix(
+        matrix: &mut MimoMatrix,
+        inputs: &[Complex<f32>],
+        rx_antennas: usize,
+    ) -> Vec<Vec<Complex<f32>>> {
+        let inputs: Vec<_> = inputs.iter().map(|x| vec![*x; 10]).collect();
+        let mut outputs = vec![vec![Complex::zero(); 10]; rx_antennas];
+        matrix.mix(&inputs, &mut outputs);
+        outputs
+    }
+
+    #[test]
+    fn identity_pairs_antennas_with_the_same_index() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut matrix = MimoMatrix::new(&MimoChannel::Identity, 2, 3, SAMP_RATE, &mut rng);
+        assert!(matrix.is_identity());
+        let (one, zero) = (Complex::new(1., 0.), Complex::zero());
+        assert_eq!(matrix.gains(), [[one, zero], [zero, one], [zero, zero]]);
+        let inputs = [Complex::new(1., 2.), Complex::new(-3., 0.5)];
+        let outputs = mix(&mut matrix, &inputs, 3);
+        assert!(outputs[0].iter().all(|o| *o == inputs[0]));
+        assert!(outputs[1].iter().all(|o| *o == inputs[1]));
+        assert!(outputs[2].iter().all(|o| o.is_zero()));
+    }
+
+    #[test]
+    fn matrix_mixes_every_tx_antenna_into_every_rx_antenna() {
+        let gains = vec![
+            vec![Complex::new(1., 0.), Complex::new(0.5, 0.)],
+            vec![Complex::new(0., 0.), Complex::new(0., -1.)],
+        ];
+        let channel = MimoChannel::Matrix(gains.clone());
+        assert!(channel.validate_antennas(2, 2).is_ok());
+        assert!(channel.validate_antennas(1, 2).is_err());
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut matrix = MimoMatrix::new(&channel, 2, 2, SAMP_RATE, &mut rng);
+        assert!(!matrix.is_identity());
+        assert_eq!(matrix.gains(), gains);
+        let inputs = [Complex::new(1., 2.), Complex::new(-3., 0.5)];
+        let outputs = mix(&mut matrix, &inputs, 2);
+        for (output, row) in outputs.iter().zip(&gains) {
+            let expected = row[0] * inputs[0] + row[1] * inputs[1];
+            assert!(output.iter().all(|o| (o - expected).norm() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn correlation_root_squares_to_the_correlation_matrix() {
+        let (rho, n) = (0.6, 4);
+        let root = correlation_root(rho, n);
+        for i in 0..n {
+            for j in 0..n {
+                let product: f64 = (0..n).map(|k| root[i][k] * root[j][k]).sum();
+                let expected = rho.powi((i as i32 - j as i32).abs());
+                assert!((product - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn rayleigh_gains_follow_the_kronecker_model() {
+        // E[h_ij conj(h_kl)] = rx_correlation^|i - k| * tx_correlation^|j - l|, averaged over many
+        // fixed channels
+        let channel = MimoChannel::Rayleigh {
+            tx_correlation: 0.7,
+            rx_correlation: 0.5,
+            max_doppler: 0.,
+        };
+        let num_channels = 4000;
+        let mut corr = vec![vec![Complex::<f64>::zero(); 4]; 4];
+        for seed in 0..num_channels {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let gains = MimoMatrix::new(&channel, 2, 2, SAMP_RATE, &mut rng).gains;
+            for (a, row) in gains.iter().zip(corr.iter_mut()) {
+                for (b, c) in gains.iter().zip(row.iter_mut()) {
+                    let prod = a * b.conj();
+                    *c += Complex::new(prod.re as f64, prod.im as f64) / num_channels as f64;
+                }
+            }
+        }
+        // Gains are in the order (rx 0, tx 0), (0, 1), (1, 0), (1, 1)
+        let index = |g: usize| ((g / 2) as i32, (g % 2) as i32);
+        for (a, row) in corr.iter().enumerate() {
+            for (b, c) in row.iter().enumerate() {
+                let ((i, j), (k, l)) = (index(a), index(b));
+                let expected = 0.5f64.powi((i - k).abs()) * 0.7f64.powi((j - l).abs());
+                assert!(
+                    (c - expected).norm() < 0.1,
+                    "{:?} instead of {}",
+                    c,
+                    expected
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1261 @@
+//! Test on a simulation of a USRP rather than a real device. `SimulatedRadioRx` and
+//! `SimulatedRadioTx` are generated based on parameters in `RadioSimulatorConfig` by
+//! `create_simulator`. For reproducible runs, use `create_seeded_simulator` or
+//! `create_simulator_with_rng` instead. To simulate more than one Tx or Rx sharing the medium, use
+//! `SimulatedNetwork`. To derive the noise power and the links' gains and delays from where the
+//! radios are, use `LinkBudget`, and to move them, give their `Node`s a `Trajectory`. Besides
+//! noise, receivers can pick up tones and other interference from an `Interferer`. Retuning an Rx
+//! disturbs its LO for a while, as set by `LoSettling`, and `RadioRx::lo_locked` reports when it has
+//! settled. Radios may have several `Antennas`, which `RadioTxMulti` and `RadioRxMulti` send and
+//! receive on, with the gains between them given by each link's `MimoChannel`. A full-duplex Rx
+//! can also hear its own Tx through `SelfInterference`. A `RadioSimulatorConfig` can be saved to and
+//! loaded from a TOML or JSON scenario file, whose timeline of `ConfigChange`s alters the
+//! simulation as it runs.
+
+mod amplifier;
+mod channel;
+mod clock;
+mod config;
+mod duplex;
+mod faults;
+mod geometry;
+mod impairments;
+mod interference;
+mod link;
+mod mimo;
+mod network;
+mod oscillator;
+mod scenario;
+mod truth;
+mod tuning;
+
+pub use amplifier::PaModel;
+pub use channel::{ChannelModel, DelayProfile, Fading, Tap};
+pub use config::{LinkConfig, RadioSimulatorConfig, RadioSimulatorConfigBuilder};
+pub use duplex::SelfInterference;
+pub use faults::{Fault, FaultInjector};
+pub use geometry::{LinkBudget, Mobility, Node, PathLoss, Trajectory};
+pub use impairments::IqImpairments;
+pub use interference::Interferer;
+pub use mimo::{Antennas, MimoChannel};
+pub use network::SimulatedNetwork;
+pub use oscillator::LoSettling;
+pub use scenario::ConfigChange;
+pub use truth::{GroundTruth, LinkTruth};
+
+use amplifier::PowerAmplifier;
+use clock::{Medium, VirtualClock};
+use config::validate_bandwidth;
+use faults::FaultState;
+use impairments::IqDistortion;
+use interference::InterfererState;
+use link::Link;
+use network::{RxParams, TxParams};
+use oscillator::Oscillator;
+use tuning::Tuning;
+
+use crate::{RadioError, RadioRx, RadioRxMulti, RadioTx, RadioTxMulti, TxMetadata};
+use num::{Complex, Zero};
+use rand::{
+    distributions::Distribution,
+    rngs::{SmallRng, StdRng, ThreadRng},
+    Rng, SeedableRng,
+};
+use rand_distr::Normal;
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+
+/// A block of samples sent by `SimulatedRadioTx` to `SimulatedRadioRx`
+struct TxBlock {
+    /// Index of the first sample in the medium's timeline. Blocks from a Tx never overlap, and any
+    /// gap between the end of one block and the start of the next is a period when the Tx was
+    /// silent
+    start: u64,
+    /// The samples of each of the Tx's antennas
+    samples: Vec<Vec<Complex<f32>>>,
+    /// Frequency and bandwidth of the Tx when it sent the block
+    tuning: Tuning,
+}
+
+/// What a Tx sends to each Rx that can hear it: the index of the Tx and a block of samples. Blocks
+/// are shared between all the receivers
+type TxMessage = (usize, Arc<TxBlock>);
+
+/// Receives the sum of the signals from all the transmitters it can hear, plus noise. Like a real
+/// radio, it samples continuously and never waits for the transmitters: whenever they are silent,
+/// it receives only noise
+pub struct SimulatedRadioRx<R: Rng> {
+    rng: R,
+    /// Device clock, which determines the timestamps of received samples
+    clock: VirtualClock,
+    /// Blocks coming in from the transmitters
+    receiver: Receiver<TxMessage>,
+    /// The time in the medium, shared by all the radios of the simulation
+    medium: Arc<Medium>,
+    /// Link from each Tx, indexed by the Tx's index. `None` if this Rx can't hear that Tx
+    links: Vec<Option<Link>>,
+    /// The index of the Tx whose signal leaks into this Rx, and the path it leaks through, if
+    /// this Rx is part of a full-duplex radio
+    self_interference: Option<(usize, Link)>,
+    /// Interference picked up in addition to the transmitters, and scratch space for their sum,
+    /// which reaches all the antennas alike
+    interferers: Vec<InterfererState>,
+    interference: Vec<Complex<f32>>,
+    /// Power of the noise in dB, and the distribution of its real and imaginary parts at each
+    /// antenna
+    noise_power: f64,
+    noise: Vec<Normal<f32>>,
+    /// The antennas, whose noise powers may be offset from `noise_power`
+    antennas: Antennas,
+    /// Phase offset of each antenna's LO, as a unit phasor
+    lo_phasors: Vec<Complex<f32>>,
+    /// DC offset and IQ imbalance of the front end
+    iq: IqDistortion,
+    /// Gain of the Rx in dB, and the corresponding amplitude gain
+    gain_db: f64,
+    gain: f32,
+    /// Largest output of the ADC (whose full scale is [-1, 1]) in quantization steps, or `None` if
+    /// the ADC is ideal
+    adc_levels: Option<f32>,
+    /// Faults to inject, if any
+    faults: Option<FaultState>,
+    /// Changes to the parameters that haven't been applied yet, as (index of the sample from which
+    /// they apply, change), in the order they will be applied
+    timeline: VecDeque<(u64, ConfigChange)>,
+    /// If `Some`, the sum of the Tx signals (before the channel, CFO, gain and noise) in each
+    /// sample returned by the last call to `recv`. For transmitters with several antennas, only
+    /// the first counts
+    clean: Option<Vec<Complex<f32>>>,
+    /// Number of samples we have sent out of Rx so far
+    tot_num_samps: u64,
+    /// The current frequency at which we are receiving. Calling SimulatedRadioRx::set_freq sets
+    /// it immediately. We don't model imperfections here
+    cur_freq: f64,
+    /// Analog bandwidth (in Hz) of the front end
+    bandwidth: f64,
+    /// Local oscillator, which settles after every retune
+    lo: Oscillator,
+    /// Buffers to store samples for returning via `RadioRx::recv` and `RadioRxMulti::recv_multi`,
+    /// one for each antenna
+    bufs: Vec<Vec<Complex<f32>>>,
+}
+
+pub struct SimulatedRadioTx {
+    /// Index of this Tx in the network
+    id: usize,
+    /// One sender for every Rx that can hear this Tx
+    senders: Vec<Sender<TxMessage>>,
+    /// The time in the medium, shared by all the radios of the simulation
+    medium: Arc<Medium>,
+    /// Device clock, used to convert burst times to sample indices
+    clock: VirtualClock,
+    /// DC offset and IQ imbalance of the front end
+    iq: IqDistortion,
+    /// Gain of the Tx in dB, and the corresponding amplitude gain. It sets how hard the PA is
+    /// driven
+    gain_db: f64,
+    gain: f32,
+    /// Power amplifier of each antenna, which follows the gain stage
+    pa: Vec<PowerAmplifier>,
+    /// Phase offset of each antenna's LO, as a unit phasor
+    lo_phasors: Vec<Complex<f32>>,
+    /// Faults to inject, if any
+    faults: Option<FaultState>,
+    /// Number of samples sent so far
+    tot_num_samps: u64,
+    /// Index (in the medium's timeline) of the sample after the last one sent so far
+    next_index: u64,
+    /// Whether the last burst is still going on, i.e. it hasn't been ended with `end_of_burst`
+    in_burst: bool,
+    /// Current frequency and analog bandwidth, which apply to the samples sent from then on
+    tuning: Tuning,
+}
+
+impl<R: Rng> SimulatedRadioRx<R> {
+    /// A receiver that hears the transmitters through `params.links`, where `tx_antennas` is the
+    /// number of antennas of each Tx. Its randomness comes from `rng`. The parameters must have
+    /// been validated
+    fn new(
+        mut rng: R,
+        receiver: Receiver<TxMessage>,
+        medium: Arc<Medium>,
+        params: &RxParams,
+        tx_antennas: &[usize],
+        samp_rate: u64,
+        start_freq: f64,
+    ) -> Self {
+        let tuning = Tuning {
+            freq: start_freq,
+            bandwidth: params.bandwidth.unwrap_or(samp_rate as f64),
+        };
+        let num_antennas = params.antennas.count;
+        let links = params
+            .links
+            .iter()
+            .zip(tx_antennas)
+            .map(|(link, tx_antennas)| {
+                link.as_ref().map(|link| {
+                    Link::new(
+                        link,
+                        samp_rate,
+                        tuning,
+                        *tx_antennas,
+                        num_antennas,
+                        &mut rng,
+                    )
+                })
+            })
+            .collect();
+        let self_interference = params.self_interference.as_ref().map(|(tx, config)| {
+            let link = Link::self_interference(
+                config,
+                samp_rate,
+                tuning,
+                tx_antennas[*tx],
+                num_antennas,
+                &mut rng,
+            );
+            (*tx, link)
+        });
+        let interferers = params
+            .interferers
+            .iter()
+            .map(|interferer| InterfererState::new(interferer, samp_rate, &mut rng))
+            .collect();
+        let mut timeline: Vec<(u64, ConfigChange)> = params
+            .timeline
+            .iter()
+            .map(|(time, change)| ((time * samp_rate as f64).round() as u64, change.clone()))
+            .collect();
+        // Stable, so changes at the same time are applied in the order they were given
+        timeline.sort_by_key(|(index, _)| *index);
+        Self {
+            rng,
+            clock: VirtualClock::new(samp_rate),
+            receiver,
+            medium,
+            links,
+            self_interference,
+            interferers,
+            interference: Vec::new(),
+            noise_power: params.noise_power,
+            noise: noise_distributions(&params.antennas, params.noise_power),
+            antennas: params.antennas.clone(),
+            lo_phasors: params.antennas.lo_phasors(),
+            iq: IqDistortion::new(&params.iq),
+            gain_db: params.gain,
+            gain: 10f32.powf(params.gain as f32 / 20.),
+            adc_levels: params
+                .adc_bits
+                .map(|bits| (2f64.powi(i32::from(bits) - 1) - 1.) as f32),
+            faults: None,
+            timeline: timeline.into(),
+            clean: None,
+            tot_num_samps: 0,
+            cur_freq: start_freq,
+            bandwidth: tuning.bandwidth,
+            lo: Oscillator::new(&params.lo_settling, samp_rate),
+            bufs: vec![Vec::new(); num_antennas],
+        }
+    }
+
+    /// Hand the blocks the transmitters have sent so far to their links, without waiting for more
+    fn receive_blocks(&mut self) {
+        // Dropped transmitters are simply silent from then on
+        while let Ok((tx, block)) = self.receiver.try_recv() {
+            // A Tx only sends to the receivers that have a link from it or that it leaks into
+            if let Some(Some(link)) = self.links.get_mut(tx) {
+                link.push(&block);
+            }
+            match &mut self.self_interference {
+                Some((leaking, link)) if *leaking == tx => link.push(&block),
+                _ => {}
+            }
+        }
+    }
+
+    /// The links from all the transmitters, followed by the self-interference path, if any
+    fn all_links_mut(&mut self) -> impl Iterator<Item = &mut Link> {
+        let self_interference = self.self_interference.iter_mut().map(|(_, link)| link);
+        self.links.iter_mut().flatten().chain(self_interference)
+    }
+
+    /// Drop samples, so that the next one received is the `index`^th
+    fn skip_to(&mut self, index: u64) {
+        self.tot_num_samps = index;
+        for link in self.all_links_mut() {
+            link.skip_to(index);
+        }
+    }
+
+    /// Apply the faults due in this call. Returns the error the call should fail with, if any
+    fn inject_faults(&mut self) -> Result<(), RadioError> {
+        let faults = match &mut self.faults {
+            Some(faults) => faults.next(self.tot_num_samps),
+            None => return Ok(()),
+        };
+        let mut res = Ok(());
+        for fault in faults {
+            match fault {
+                Fault::Error(err) => res = res.and(Err(err)),
+                Fault::Drop(len) => self.skip_to(self.tot_num_samps + len),
+                Fault::TimeJump(secs) => {
+                    let now = self.clock.time(self.tot_num_samps);
+                    self.clock.set_time(self.tot_num_samps, now + secs);
+                }
+            }
+        }
+        res
+    }
+
+    /// Apply the changes in the timeline that are due by the next sample
+    fn apply_changes(&mut self) {
+        while self
+            .timeline
+            .front()
+            .is_some_and(|(index, _)| *index <= self.tot_num_samps)
+        {
+            match self.timeline.pop_front().unwrap().1 {
+                ConfigChange::NoisePower(noise_power) => {
+                    self.noise_power = noise_power;
+                    self.noise = noise_distributions(&self.antennas, noise_power);
+                }
+                ConfigChange::RxGain(gain) => self.set_gain(gain),
+                ConfigChange::LinkGain(gain) => {
+                    for link in self.links.iter_mut().flatten() {
+                        link.set_gain(gain);
+                    }
+                }
+                ConfigChange::Channel(channel) => {
+                    for link in self.links.iter_mut().flatten() {
+                        link.set_channel(&channel, &mut self.rng);
+                    }
+                }
+                ConfigChange::AddInterferer(interferer) => {
+                    let samp_rate = self.medium.samp_rate();
+                    let interferer = InterfererState::new(&interferer, samp_rate, &mut self.rng);
+                    self.interferers.push(interferer);
+                }
+            }
+        }
+    }
+
+    /// Fill `bufs`, one for each antenna, with the next `len` samples. A change in the timeline
+    /// that is due in the middle of them splits them into blocks, so that it applies from the
+    /// sample it is due at
+    fn receive(&mut self, bufs: &mut [Vec<Complex<f32>>], len: usize) {
+        let end = self.tot_num_samps + len as u64;
+        self.apply_changes();
+        if self.timeline.front().is_none_or(|(index, _)| *index >= end) {
+            for buf in bufs.iter_mut() {
+                buf.resize(len, Complex::zero());
+            }
+            self.receive_block(bufs);
+            return;
+        }
+
+        let mut blocks = vec![Vec::new(); bufs.len()];
+        let mut clean = self.clean.as_ref().map(|_| Vec::with_capacity(len));
+        for buf in bufs.iter_mut() {
+            buf.clear();
+        }
+        while self.tot_num_samps < end {
+            self.apply_changes();
+            let block_end = self
+                .timeline
+                .front()
+                .map_or(end, |(index, _)| (*index).min(end));
+            let block_len = (block_end - self.tot_num_samps) as usize;
+            for block in &mut blocks {
+                block.resize(block_len, Complex::zero());
+            }
+            self.receive_block(&mut blocks);
+            for (buf, block) in bufs.iter_mut().zip(&blocks) {
+                buf.extend_from_slice(block);
+            }
+            if let (Some(clean), Some(block)) = (&mut clean, &self.clean) {
+                clean.extend_from_slice(block);
+            }
+        }
+        if clean.is_some() {
+            self.clean = clean;
+        }
+    }
+
+    /// Fill `bufs`, one for each antenna, with the next samples
+    fn receive_block(&mut self, bufs: &mut [Vec<Complex<f32>>]) {
+        let index = self.tot_num_samps;
+        let len = bufs[0].len();
+        // Several random numbers are needed for every sample (noise, CFO drift and phase noise),
+        // and `rng` may be slow. Draw them from a fast generator seeded from it
+        let mut rng = SmallRng::from_rng(&mut self.rng).unwrap();
+
+        // Noise, which is independent at each antenna
+        for (buf, noise) in bufs.iter_mut().zip(&self.noise) {
+            for samp in buf.iter_mut() {
+                *samp = Complex::new(noise.sample(&mut rng), noise.sample(&mut rng));
+            }
+        }
+
+        // Superposition of all the transmitters, including this radio's own Tx if it leaks
+        for link in self.all_links_mut() {
+            link.add_block(index, bufs, &mut rng);
+        }
+        let bandwidth = self.bandwidth;
+        if let [buf] = bufs {
+            for interferer in &mut self.interferers {
+                interferer.add_block(buf, bandwidth, &mut rng);
+            }
+        } else if !self.interferers.is_empty() {
+            self.interference.clear();
+            self.interference.resize(len, Complex::zero());
+            for interferer in &mut self.interferers {
+                interferer.add_block(&mut self.interference, bandwidth, &mut rng);
+            }
+            for buf in bufs.iter_mut() {
+                for (samp, interference) in buf.iter_mut().zip(&self.interference) {
+                    *samp += interference;
+                }
+            }
+        }
+        if let Some(clean) = &mut self.clean {
+            clean.clear();
+            clean.resize(len, Complex::zero());
+            for link in self.links.iter().flatten() {
+                for (samp, link_samp) in clean.iter_mut().zip(link.clean()) {
+                    *samp += link_samp;
+                }
+            }
+        }
+
+        for (buf, lo_phasor) in bufs.iter_mut().zip(&self.lo_phasors) {
+            // Everything passes through the mixer, including the noise of the LNA before it
+            self.lo.process_block(index, buf);
+            if *lo_phasor != Complex::new(1., 0.) {
+                buf.iter_mut().for_each(|samp| *samp *= lo_phasor);
+            }
+
+            // The front end amplifies and distorts the signal and noise alike
+            scale(buf, self.gain);
+            self.iq.process_block(buf);
+
+            if let Some(levels) = self.adc_levels {
+                let quantize = |x: f32| (x.clamp(-1., 1.) * levels).round() / levels;
+                for samp in buf.iter_mut() {
+                    *samp = Complex::new(quantize(samp.re), quantize(samp.im));
+                }
+            }
+        }
+        self.tot_num_samps += len as u64;
+    }
+
+    /// Index of the current sample, for the purpose of the LO's sensor. In real time mode, the LO
+    /// settles even while the caller isn't receiving
+    fn now(&self) -> u64 {
+        match self.medium.buffer_len() {
+            Some(_) => self.medium.now().max(self.tot_num_samps),
+            None => self.tot_num_samps,
+        }
+    }
+
+    fn tuning(&self) -> Tuning {
+        Tuning {
+            freq: self.cur_freq,
+            bandwidth: self.bandwidth,
+        }
+    }
+
+    /// Get the gain in dB
+    pub fn get_gain(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Set the gain in dB. It applies from the next sample onwards
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain_db = gain;
+        self.gain = 10f32.powf(gain as f32 / 20.);
+    }
+
+    /// Set the analog bandwidth (in Hz) of the front end, which must be positive and at most the
+    /// sample rate. Signals outside it are filtered out. It applies from the next sample onwards
+    pub fn set_bandwidth(&mut self, bandwidth: f64) -> Result<(), RadioError> {
+        validate_bandwidth(Some(bandwidth), self.medium.samp_rate())?;
+        self.bandwidth = bandwidth;
+        let tuning = self.tuning();
+        for link in self.all_links_mut() {
+            link.set_tuning(tuning);
+        }
+        Ok(())
+    }
+
+    /// The true state of the simulation, as of the next sample to be received
+    pub fn ground_truth(&self) -> GroundTruth {
+        GroundTruth {
+            tot_num_samps: self.tot_num_samps,
+            noise_power: self.noise_power,
+            gain: self.gain_db,
+            lo_locked: self.lo.locked(self.now()),
+            links: self
+                .links
+                .iter()
+                .map(|link| link.as_ref().map(|link| link.truth(self.noise_power)))
+                .collect(),
+            self_interference: self
+                .self_interference
+                .as_ref()
+                .map(|(_, link)| link.truth(self.noise_power)),
+        }
+    }
+
+    /// Start or stop recording the clean signal, i.e. the sum of the signals the transmitters
+    /// sent, aligned to the Rx's timeline but before the channel, CFO, gain and noise
+    pub fn record_clean_signal(&mut self, enable: bool) {
+        self.clean = if enable { Some(Vec::new()) } else { None };
+    }
+
+    /// If recording, the clean signal in each sample returned by the last call to `recv`
+    pub fn clean_signal(&self) -> Option<&[Complex<f32>]> {
+        self.clean.as_deref()
+    }
+
+    /// Inject faults into subsequent calls to `recv`. Sample counts are relative to
+    /// `tot_num_samps`. Replaces any previous faults
+    pub fn set_faults(&mut self, faults: &FaultInjector) -> Result<(), RadioError> {
+        faults.validate()?;
+        self.faults = Some(FaultState::new(faults));
+        Ok(())
+    }
+}
+
+impl SimulatedRadioTx {
+    /// A transmitter that no Rx can hear yet, which starts at frequency `start_freq`
+    fn new(
+        id: usize,
+        params: &TxParams,
+        start_freq: f64,
+        clock: VirtualClock,
+        medium: Arc<Medium>,
+    ) -> Self {
+        let tuning = Tuning {
+            freq: start_freq,
+            bandwidth: params.bandwidth.unwrap_or(medium.samp_rate() as f64),
+        };
+        Self {
+            id,
+            iq: IqDistortion::new(&params.iq),
+            gain_db: params.gain,
+            gain: 10f32.powf(params.gain as f32 / 20.),
+            pa: (0..params.antennas.count)
+                .map(|_| PowerAmplifier::new(&params.pa))
+                .collect(),
+            lo_phasors: params.antennas.lo_phasors(),
+            senders: Vec::new(),
+            medium,
+            clock,
+            faults: None,
+            tot_num_samps: 0,
+            next_index: 0,
+            in_burst: false,
+            tuning,
+        }
+    }
+
+    /// Get the gain in dB
+    pub fn get_gain(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Set the gain in dB. It applies from the next burst onwards
+    pub fn set_gain(&mut self, gain: f64) {
+        self.gain_db = gain;
+        self.gain = 10f32.powf(gain as f32 / 20.);
+    }
+
+    /// Set the analog bandwidth (in Hz) of the front end, which must be positive and at most the
+    /// sample rate. It applies to the samples sent from then on
+    pub fn set_bandwidth(&mut self, bandwidth: f64) -> Result<(), RadioError> {
+        validate_bandwidth(Some(bandwidth), self.medium.samp_rate())?;
+        self.tuning.bandwidth = bandwidth;
+        Ok(())
+    }
+
+    /// Inject faults into subsequent calls to `send_burst`. Sample counts are of the samples
+    /// passed to `send_burst` so far. Replaces any previous faults
+    pub fn set_faults(&mut self, faults: &FaultInjector) -> Result<(), RadioError> {
+        faults.validate()?;
+        self.faults = Some(FaultState::new(faults));
+        Ok(())
+    }
+
+    /// Put the Tx on the same device as `rx`, so that the times of its timed bursts are in `rx`'s
+    /// device time, including after `RadioRx::set_time_now`. The Tx created with an Rx by
+    /// `create_simulator` already shares its clock
+    pub fn share_clock<R: Rng>(&mut self, rx: &SimulatedRadioRx<R>) {
+        self.clock = rx.clock.clone();
+    }
+}
+
+impl<R: Rng> RadioRx for SimulatedRadioRx<R> {
+    /// Sets the time of the device, which also applies to timed bursts of the transmitters that
+    /// share its clock (see `SimulatedRadioTx::share_clock`). The next sample received will have
+    /// timestamp `now`
+    fn set_time_now(&mut self, now: f64) {
+        self.clock.set_time(self.tot_num_samps, now);
+    }
+
+    fn tot_num_samps(&self) -> u64 {
+        self.tot_num_samps
+    }
+
+    fn recv(&mut self, len: usize) -> Result<(&[Complex<f32>], u64), RadioError> {
+        let (bufs, time_spec) = self.recv_multi(len)?;
+        Ok((&bufs[0], time_spec))
+    }
+
+    /// Only the transmitters whose band overlaps the Rx's new band can be heard, shifted by the
+    /// difference in frequency
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError> {
+        self.cur_freq = freq;
+        let tuning = self.tuning();
+        for link in self.all_links_mut() {
+            link.set_tuning(tuning);
+        }
+        self.lo.retune(self.tot_num_samps, &mut self.rng);
+        Ok(())
+    }
+
+    /// False until the settling time of the LO has passed since the last `set_freq`
+    fn lo_locked(&mut self) -> Result<bool, RadioError> {
+        Ok(self.lo.locked(self.now()))
+    }
+}
+
+impl<R: Rng> RadioRxMulti for SimulatedRadioRx<R> {
+    fn num_channels(&self) -> usize {
+        self.bufs.len()
+    }
+
+    /// In real time mode, this waits till the samples have been received. If the caller fell
+    /// further behind than the buffer allows, the buffered samples are dropped and
+    /// `RadioError::Overflow` is returned. The next call continues from the current time
+    fn recv_multi(&mut self, len: usize) -> Result<(&[Vec<Complex<f32>>], u64), RadioError> {
+        self.receive_blocks();
+        self.inject_faults()?;
+        if let Some(buffer_len) = self.medium.buffer_len() {
+            let now = self.medium.now();
+            if now > self.tot_num_samps + buffer_len {
+                self.skip_to(now);
+                return Err(RadioError::Overflow);
+            }
+            self.medium.wait_until(self.tot_num_samps + len as u64);
+            // More blocks may have arrived while we waited
+            self.receive_blocks();
+        }
+
+        let time_spec = self.clock.time_us(self.tot_num_samps);
+        let mut bufs = std::mem::take(&mut self.bufs);
+        self.receive(&mut bufs, len);
+        self.bufs = bufs;
+        // Time in the medium moves on as far as the furthest Rx has received
+        self.medium.received(self.tot_num_samps);
+
+        Ok((&self.bufs, time_spec))
+    }
+}
+
+/// Distribution of the real and imaginary parts of the noise at each antenna, for a noise power of
+/// `noise_power` dB
+fn noise_distributions(antennas: &Antennas, noise_power: f64) -> Vec<Normal<f32>> {
+    // Noise power is split equally between the real and imaginary parts
+    antennas
+        .noise_powers(noise_power)
+        .iter()
+        .map(|power| {
+            let std = (10f64.powf(power / 10.) / 2.).sqrt() as f32;
+            Normal::new(0., std).unwrap()
+        })
+        .collect()
+}
+
+/// Multiply the samples by an amplitude gain, in place
+fn scale(samps: &mut [Complex<f32>], gain: f32) {
+    if gain != 1. {
+        samps.iter_mut().for_each(|samp| *samp *= gain);
+    }
+}
+
+impl SimulatedRadioTx {
+    /// Send one buffer of samples per antenna, all of the same length, as described by `md`. If
+    /// there are fewer buffers than antennas, the remaining antennas are silent
+    fn send_antennas(
+        &mut self,
+        data: &[&[Complex<f32>]],
+        md: &TxMetadata,
+    ) -> Result<(), RadioError> {
+        // Number of samples at the start of `data` that are lost
+        let mut dropped = 0;
+        if let Some(faults) = &mut self.faults {
+            let mut res = Ok(());
+            for fault in faults.next(self.tot_num_samps) {
+                match fault {
+                    Fault::Error(err) => res = res.and(Err(err)),
+                    Fault::Drop(len) => dropped += len,
+                    Fault::TimeJump(secs) => {
+                        let index = self.medium.now();
+                        let now = self.clock.time(index);
+                        self.clock.set_time(index, now + secs);
+                    }
+                }
+            }
+            res?;
+        }
+
+        let now = self.medium.now();
+        let underflow = self.medium.buffer_len().is_some()
+            && self.in_burst
+            && md.time.is_none()
+            && now > self.next_index;
+        let earliest = self.next_index.max(now);
+        let start = match md.time {
+            Some(time) => {
+                let start = self.clock.index(time);
+                // Can't go back in time, or transmit over samples that were already sent
+                if start < earliest as f64 {
+                    return Err(RadioError::LateCommand);
+                }
+                start as u64
+            }
+            None => earliest,
+        };
+        if start != self.next_index {
+            // The PA was idle since the last burst
+            self.pa.iter_mut().for_each(PowerAmplifier::reset);
+        }
+        let len = data[0].len();
+        self.next_index = start + len as u64;
+        self.tot_num_samps += len as u64;
+
+        // Each antenna's IQ modulator is followed by its gain stage and PA. Antennas without
+        // samples are silent, apart from the DC offset
+        let dropped = dropped.min(len as u64) as usize;
+        let samples = (0..self.pa.len())
+            .map(|antenna| {
+                let mut samples = match data.get(antenna) {
+                    Some(data) => data[dropped..].to_vec(),
+                    None => vec![Complex::zero(); len - dropped],
+                };
+                if self.lo_phasors[antenna] != Complex::new(1., 0.) {
+                    let lo_phasor = self.lo_phasors[antenna];
+                    samples.iter_mut().for_each(|samp| *samp *= lo_phasor);
+                }
+                self.iq.process_block(&mut samples);
+                scale(&mut samples, self.gain);
+                self.pa[antenna].process_block(&mut samples);
+                samples
+            })
+            .collect();
+        let block = Arc::new(TxBlock {
+            start: start + dropped as u64,
+            samples,
+            tuning: self.tuning,
+        });
+        // Receivers may come and go, but it is an error if nobody is left to hear us
+        let mut num_sent = 0;
+        for sender in &self.senders {
+            if sender.send((self.id, block.clone())).is_ok() {
+                num_sent += 1;
+            }
+        }
+        if num_sent == 0 && !self.senders.is_empty() {
+            return Err(RadioError::Disconnected);
+        }
+        self.in_burst = !md.end_of_burst;
+
+        // Wait till the samples fit in the buffer
+        if let Some(buffer_len) = self.medium.buffer_len() {
+            self.medium
+                .wait_until(self.next_index.saturating_sub(buffer_len));
+        }
+        if underflow {
+            return Err(RadioError::Underflow);
+        }
+        Ok(())
+    }
+}
+
+impl RadioTx for SimulatedRadioTx {
+    /// Timed bursts start when the device clock reaches `time`, and the Rx sees them after the
+    /// link's delay. Untimed samples are sent now, i.e. at the furthest point any Rx has
+    /// received up to (or the wall clock's time in real time mode), or directly after the
+    /// previously sent ones if those haven't all been received yet.
+    ///
+    /// In real time mode, this waits till there is room for the samples in the Tx's buffer. If a
+    /// burst that hasn't ended runs out of samples before this is called, the samples are sent
+    /// late and `RadioError::Underflow` is returned
+    fn send_burst(&mut self, data: &[Complex<f32>], md: &TxMetadata) -> Result<(), RadioError> {
+        self.send_antennas(&[data], md)
+    }
+
+    /// Applies to the samples sent from then on. Receivers tuned elsewhere hear the signal
+    /// shifted by the difference in frequency, if at all
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError> {
+        self.tuning.freq = freq;
+        Ok(())
+    }
+}
+
+impl RadioTxMulti for SimulatedRadioTx {
+    fn num_channels(&self) -> usize {
+        self.pa.len()
+    }
+
+    /// Like `RadioTx::send_burst`, with one buffer for each antenna
+    fn send_burst_multi(
+        &mut self,
+        data: &[&[Complex<f32>]],
+        md: &TxMetadata,
+    ) -> Result<(), RadioError> {
+        if data.len() != self.pa.len() || data.iter().any(|d| d.len() != data[0].len()) {
+            return Err(RadioError::ChannelMismatch);
+        }
+        self.send_antennas(data, md)
+    }
+}
+
+/// Create a simulated Tx/Rx pair whose randomness comes from the thread-local RNG, so every run is
+/// different
+pub fn create_simulator(
+    config: &RadioSimulatorConfig,
+) -> (SimulatedRadioTx, SimulatedRadioRx<ThreadRng>) {
+    create_simulator_with_rng(config, rand::thread_rng())
+}
+
+/// Create a simulated Tx/Rx pair that is seeded with `seed`. For a given version of this crate,
+/// the same `seed` and `config` produce bit-identical samples at the Rx, provided the same calls
+/// are made to the Tx and Rx, in the same order and from a single thread, and the simulation isn't
+/// paced in real time. Otherwise what the Rx hears depends on when each block reaches it, and a
+/// burst sent without a time starts at whichever sample the Rx has got to
+pub fn create_seeded_simulator(
+    config: &RadioSimulatorConfig,
+    seed: u64,
+) -> (SimulatedRadioTx, SimulatedRadioRx<StdRng>) {
+    create_simulator_with_rng(config, StdRng::seed_from_u64(seed))
+}
+
+/// Create a simulated Tx/Rx pair that draws all its randomness (start offset, CFO, phase, noise)
+/// from `rng`. The output is a deterministic function of `config`, the state of `rng` and the
+/// samples sent by the Tx
+pub fn create_simulator_with_rng<R: Rng>(
+    config: &RadioSimulatorConfig,
+    mut rng: R,
+) -> (SimulatedRadioTx, SimulatedRadioRx<R>) {
+    // The random start time offset is simulated as extra delay on the link
+    let link = LinkConfig {
+        delay: config.link().delay + rng.gen::<u64>() % config.max_start_time_offset(),
+        ..config.link().clone()
+    };
+    let tx = TxParams {
+        iq: config.tx_impairments().clone(),
+        gain: config.tx_gain(),
+        pa: config.pa_model().clone(),
+        bandwidth: config.tx_bandwidth(),
+        antennas: config.tx_antennas().clone(),
+    };
+    let rx = RxParams {
+        noise_power: config.noise_power(),
+        iq: config.rx_impairments().clone(),
+        gain: config.rx_gain(),
+        adc_bits: config.adc_bits(),
+        bandwidth: config.rx_bandwidth(),
+        lo_settling: config.lo_settling().clone(),
+        interferers: config.interferers().to_vec(),
+        antennas: config.rx_antennas().clone(),
+        // A full-duplex radio hears its own Tx only through the leakage
+        links: vec![config.self_interference().is_none().then_some(link)],
+        self_interference: config.self_interference().map(|si| (0, si.clone())),
+        timeline: config.timeline().to_vec(),
+    };
+    let (mut txs, mut rxs) = network::connect(
+        config.samp_rate(),
+        config.start_freq(),
+        config.real_time(),
+        &[tx],
+        vec![(rng, rx)],
+    );
+    let (mut tx, rx) = (txs.pop().unwrap(), rxs.pop().unwrap());
+    // The Tx and Rx are on the same device, and hence share a clock
+    tx.share_clock(&rx);
+    (tx, rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use std::time::Duration;
+
+    /// Send a burst and receive it, with the calls in the same order every time
+    fn run(seed: u64) -> Vec<Complex<f32>> {
+        let config = RadioSimulatorConfig::builder()
+            .max_start_time_offset(100)
+            .max_cfo(1e3)
+            .build()
+            .unwrap();
+        let (mut tx, mut rx) = create_seeded_simulator(&config, seed);
+        tx.send_burst(&[Complex::new(0.5, 0.); 1000], &TxMetadata::default())
+            .unwrap();
+        rx.recv(2000).unwrap().0.to_vec()
+    }
+
+    #[test]
+    fn seeded_simulators_are_reproducible() {
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn recv_returns_the_requested_samples_on_every_channel() {
+        let config = RadioSimulatorConfig::builder()
+            .rx_antennas(Antennas {
+                count: 3,
+                ..Antennas::default()
+            })
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        assert_eq!(rx.num_channels(), 3);
+        for len in [250, 1, 4096] {
+            let (samples, _) = rx.recv_multi(len).unwrap();
+            assert_eq!(samples.len(), 3);
+            assert!(samples.iter().all(|channel| channel.len() == len));
+        }
+
+        let (_tx, mut rx) =
+            create_seeded_simulator(&RadioSimulatorConfig::builder().build().unwrap(), 0);
+        assert_eq!(rx.num_channels(), 1);
+        for len in [250, 1, 4096] {
+            assert_eq!(rx.recv(len).unwrap().0.len(), len);
+            assert_eq!(rx.recv_multi(len).unwrap().0.len(), 1);
+        }
+    }
+
+    #[test]
+    fn sample_clock_offset_restarts_between_bursts() {
+        // The Rx's clock is 1000 ppm fast, so a burst of 2000 samples lasts 2002 at the Rx
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .sfo(1000.)
+            .build()
+            .unwrap();
+        let (mut tx, mut rx) = create_seeded_simulator(&config, 0);
+        let burst = [Complex::new(0.5, 0.); 2000];
+        for time in [0., 0.01] {
+            let md = TxMetadata {
+                time: Some(time),
+                start_of_burst: true,
+                end_of_burst: true,
+            };
+            tx.send_burst(&burst, &md).unwrap();
+        }
+        let samples = rx.recv(15_000).unwrap().0.to_vec();
+        let audible = |range: std::ops::Range<usize>| {
+            let first = range.clone().find(|&i| samples[i].norm() > 0.25);
+            let last = range.rev().find(|&i| samples[i].norm() > 0.25);
+            (first.unwrap(), last.unwrap())
+        };
+        assert_eq!(audible(0..5000), (0, 2001));
+        // After the gap, the second burst starts at the sample it was sent for rather than 10
+        // samples later
+        assert_eq!(audible(5000..15_000), (10_000, 12_001));
+    }
+
+    #[test]
+    fn zeros_inside_a_burst_keep_the_sample_clock_offset() {
+        // Samples with a value of zero are part of the burst, not a silent gap that restarts the
+        // resampler
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .sfo(9000.)
+            .build()
+            .unwrap();
+        let last_audible = |burst: &[Complex<f32>]| {
+            let (mut tx, mut rx) = create_seeded_simulator(&config, 0);
+            let md = TxMetadata {
+                time: Some(0.),
+                start_of_burst: true,
+                end_of_burst: true,
+            };
+            tx.send_burst(burst, &md).unwrap();
+            let samples = rx.recv(5000).unwrap().0.to_vec();
+            (0..5000).rev().find(|&i| samples[i].norm() > 0.25).unwrap()
+        };
+        let mut burst = vec![Complex::new(0.5, 0.); 2000];
+        assert_eq!(last_audible(&burst), 2017);
+        for samp in &mut burst[1000..1010] {
+            *samp = Complex::zero();
+        }
+        assert_eq!(last_audible(&burst), 2017);
+    }
+
+    #[test]
+    fn ground_truth_reproduces_the_received_signal() {
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(-200.)
+            .max_start_time_offset(50)
+            .max_cfo(10e3)
+            .add_multipath(3e-6, Complex::new(0.3, -0.2))
+            .build()
+            .unwrap();
+        let (mut tx, mut rx) = create_seeded_simulator(&config, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let burst: Vec<_> = (0..3000)
+            .map(|_| Complex::new(rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5))
+            .collect();
+        tx.send_burst(&burst, &TxMetadata::default()).unwrap();
+        rx.record_clean_signal(true);
+        rx.recv(1000).unwrap();
+        let mut clean = rx.clean_signal().unwrap().to_vec();
+
+        // The truth as of the next sample describes the next block
+        let truth = rx.ground_truth().links[0].clone().unwrap();
+        assert!(truth.delay < 50);
+        assert_eq!(
+            clean.iter().position(|s| !s.is_zero()),
+            Some(truth.delay as usize)
+        );
+        assert!(truth.cfo.abs() <= 10e3);
+        assert_eq!(truth.taps.len(), 2);
+        let received = rx.recv(1000).unwrap().0.to_vec();
+        clean.extend_from_slice(rx.clean_signal().unwrap());
+        let cfo = 2. * PI * truth.cfo / 1e6;
+        for (i, samp) in received.iter().enumerate() {
+            let mut expected = Complex::zero();
+            for (delay, gain) in &truth.taps {
+                expected += clean[1000 + i - (delay * 1e6).round() as usize] * gain;
+            }
+            expected *= Complex::from_polar(&1., &((truth.phase + cfo * i as f64) as f32));
+            assert!((samp - expected).norm() < 1e-4, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn full_duplex_radio_hears_only_its_leakage() {
+        // The link's random start offset and CFO don't apply to the Tx's own signal
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .max_start_time_offset(100)
+            .max_cfo(10e3)
+            .self_interference(SelfInterference {
+                attenuation: 30.,
+                delay: 5e-6,
+                reflections: Vec::new(),
+            })
+            .build()
+            .unwrap();
+        let (mut tx, mut rx) = create_seeded_simulator(&config, 0);
+        tx.send_burst(&[Complex::new(1., 0.); 200], &TxMetadata::default())
+            .unwrap();
+        let truth = rx.ground_truth();
+        assert_eq!(truth.links, [None]);
+        let leakage = truth.self_interference.unwrap();
+        assert_eq!(leakage.cfo, 0.);
+        assert_eq!(leakage.taps.len(), 1);
+        assert_eq!(leakage.taps[0].0, 5e-6);
+
+        let samples = rx.recv(100).unwrap().0.to_vec();
+        assert!(samples[..5].iter().all(|s| s.is_zero()));
+        let level = 10f32.powf(-30. / 20.);
+        for samp in &samples[5..] {
+            assert!((samp.norm() - level).abs() < 1e-5);
+            assert!((samp - samples[5]).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn interferers_follow_the_rx_tuning() {
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .add_interferer(Interferer::Tone {
+                power: 0.,
+                offset: 1e5,
+            })
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        // The tone stays 100 kHz above whatever frequency the Rx is tuned to
+        for freq in [2.4e9, 2.45e9, 2.4e9 + 1e5] {
+            rx.set_freq(freq).unwrap();
+            let samples = rx.recv(100).unwrap().0;
+            for pair in samples.windows(2) {
+                assert!(((pair[1] / pair[0]).arg() - 0.2 * PI as f32).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn real_time_rx_overflows_when_it_falls_behind() {
+        // Buffers 10 ms of samples
+        let config = RadioSimulatorConfig::builder()
+            .real_time(0.01)
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        rx.recv(1000).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(rx.recv(1000).unwrap_err(), RadioError::Overflow);
+        // The buffered samples are dropped, and receiving continues from the current time
+        let (_, time) = rx.recv(1000).unwrap();
+        assert!(time >= 50_000, "time {}", time);
+    }
+
+    #[test]
+    fn real_time_tx_underflows_in_a_gap_in_a_burst() {
+        let config = RadioSimulatorConfig::builder()
+            .real_time(0.01)
+            .build()
+            .unwrap();
+        let (mut tx, _rx) = create_seeded_simulator(&config, 0);
+        let samples = [Complex::new(0.5, 0.); 1000];
+        let md = |start_of_burst, end_of_burst| TxMetadata {
+            time: None,
+            start_of_burst,
+            end_of_burst,
+        };
+        tx.send_burst(&samples, &md(true, false)).unwrap();
+        // The burst's 1 ms of samples run out long before the next ones arrive. Send 5 ms then,
+        // which leaves time to continue
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            tx.send_burst(&[Complex::new(0.5, 0.); 5000], &md(false, false)),
+            Err(RadioError::Underflow)
+        );
+        // Continuing without a gap is fine, and so is a gap between bursts
+        tx.send_burst(&samples, &md(false, true)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        tx.send_burst(&samples, &md(true, true)).unwrap();
+    }
+
+    #[test]
+    fn mimo_tx_sends_on_every_antenna() {
+        let two = Antennas {
+            count: 2,
+            ..Antennas::default()
+        };
+        let gains = vec![
+            vec![Complex::new(1., 0.), Complex::new(0.5, 0.)],
+            vec![Complex::new(0., 0.), Complex::new(0., -1.)],
+        ];
+        let config = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .tx_antennas(two.clone())
+            .rx_antennas(two)
+            .mimo_channel(MimoChannel::Matrix(gains.clone()))
+            .build()
+            .unwrap();
+        let (mut tx, mut rx) = create_seeded_simulator(&config, 0);
+        let (a, b) = ([Complex::new(1., 0.); 100], [Complex::new(0., 0.5); 100]);
+        let md = TxMetadata::default();
+        // One buffer per antenna, all of the same length
+        assert_eq!(
+            tx.send_burst_multi(&[&a], &md),
+            Err(RadioError::ChannelMismatch)
+        );
+        assert_eq!(
+            tx.send_burst_multi(&[&a, &b[..50]], &md),
+            Err(RadioError::ChannelMismatch)
+        );
+        tx.send_burst_multi(&[&a, &b], &md).unwrap();
+
+        // Each Rx antenna hears the mix of the Tx's antennas, rotated by the link's phase
+        let bufs = rx.recv_multi(100).unwrap().0;
+        let mixed = |row: &[Complex<f32>]| row[0] * a[0] + row[1] * b[0];
+        let phase = bufs[0][0] / mixed(&gains[0]);
+        assert!((phase.norm() - 1.).abs() < 1e-5);
+        for (buf, row) in bufs.iter().zip(&gains) {
+            assert!(buf.iter().all(|s| (s - mixed(row) * phase).norm() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn lo_locks_after_its_settling_time() {
+        let config = RadioSimulatorConfig::builder()
+            .lo_settling(LoSettling {
+                settle_time: 1e-3,
+                max_freq_error: 1e3,
+            })
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        // Locked until the first retune
+        assert!(rx.lo_locked().unwrap());
+        rx.recv(500).unwrap();
+        rx.set_freq(2.41e9).unwrap();
+        // The LO takes 1000 samples to lock
+        assert!(!rx.lo_locked().unwrap());
+        rx.recv(999).unwrap();
+        assert!(!rx.lo_locked().unwrap());
+        assert!(!rx.ground_truth().lo_locked);
+        rx.recv(1).unwrap();
+        assert!(rx.lo_locked().unwrap());
+        assert!(rx.ground_truth().lo_locked);
+
+        // Every retune starts over
+        rx.set_freq(2.42e9).unwrap();
+        assert!(!rx.lo_locked().unwrap());
+        rx.recv(1000).unwrap();
+        assert!(rx.lo_locked().unwrap());
+    }
+
+    #[test]
+    fn adc_quantizes_and_clips() {
+        // A signal loud enough to clip, received with an ideal ADC and a 4-bit one
+        let receive = |adc_bits: Option<u8>| {
+            let builder = RadioSimulatorConfig::builder().rx_gain(6.);
+            let builder = match adc_bits {
+                Some(bits) => builder.adc_bits(bits),
+                None => builder,
+            };
+            let (mut tx, mut rx) = create_seeded_simulator(&builder.build().unwrap(), 0);
+            assert_eq!(rx.get_gain(), 6.);
+            let burst: Vec<_> = (0..1000)
+                .map(|i| Complex::from_polar(&(i as f32 / 1000.), &(i as f32 * 0.1)))
+                .collect();
+            tx.send(&burst).unwrap();
+            rx.recv(1000).unwrap().0.to_vec()
+        };
+        let (ideal, quantized) = (receive(None), receive(Some(4)));
+        // 7 levels on either side of 0
+        let quantize = |x: f32| (x.clamp(-1., 1.) * 7.).round() / 7.;
+        for (ideal, quantized) in ideal.iter().zip(&quantized) {
+            assert_eq!(quantized.re, quantize(ideal.re));
+            assert_eq!(quantized.im, quantize(ideal.im));
+        }
+        assert!(ideal.iter().any(|s| s.re.abs() > 1.5));
+        assert!(quantized
+            .iter()
+            .all(|s| s.re.abs() <= 1. && s.im.abs() <= 1.));
+    }
+
+    #[test]
+    fn scripted_faults_change_timestamps() {
+        let config = RadioSimulatorConfig::builder()
+            .samp_rate(1_000_000)
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        let faults = FaultInjector::new()
+            .at(1000, Fault::Drop(500))
+            .at(2000, Fault::TimeJump(1.));
+        rx.set_faults(&faults).unwrap();
+
+        let mut times = Vec::new();
+        for _ in 0..4 {
+            let (samples, time) = rx.recv(1000).unwrap();
+            assert_eq!(samples.len(), 1000);
+            times.push(time);
+        }
+        // The dropped samples are skipped, and then the clock jumps by a second
+        assert_eq!(times, [0, 1500, 1_002_500, 1_003_500]);
+    }
+}
@@ -0,0 +1,518 @@
+//! A simulated medium shared by any number of transmitters and receivers. Each Tx -> Rx link has
+//! its own gain, delay, CFO and multipath, and every Rx hears the sum of the signals on its links
+//! plus its own noise. This allows testing MAC protocols, collisions and interference handling
+//! entirely in software.
+
+use super::amplifier::PaModel;
+use super::clock::{Medium, VirtualClock};
+use super::config::{
+    validate_adc_bits, validate_bandwidth, validate_gain, validate_noise_power, validate_radio,
+    validate_real_time, LinkConfig,
+};
+use super::duplex::SelfInterference;
+use super::impairments::IqImpairments;
+use super::interference::Interferer;
+use super::mimo::Antennas;
+use super::oscillator::LoSettling;
+use super::scenario::{validate_timeline, ConfigChange};
+use super::{SimulatedRadioRx, SimulatedRadioTx};
+use crate::RadioError;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+/// Parameters of one simulated transmitter
+#[derive(Clone, Debug, Default)]
+pub(super) struct TxParams {
+    /// DC offset and IQ imbalance of the Tx path
+    pub iq: IqImpairments,
+    /// Initial gain (in dB) of the Tx, which is applied before the PA
+    pub gain: f64,
+    /// The Tx's power amplifier
+    pub pa: PaModel,
+    /// Analog bandwidth (in Hz) of the Tx path, or `None` for the sample rate
+    pub bandwidth: Option<f64>,
+    /// The Tx's antennas
+    pub antennas: Antennas,
+}
+
+/// Parameters of one simulated receiver
+#[derive(Clone, Debug)]
+pub(super) struct RxParams {
+    /// Noise power (in dB) relative to a unit power signal
+    pub noise_power: f64,
+    /// DC offset and IQ imbalance of the Rx path
+    pub iq: IqImpairments,
+    /// Initial gain (in dB) of the Rx
+    pub gain: f64,
+    /// Resolution of the ADC in bits, or `None` for an ideal ADC that neither quantizes nor clips
+    pub adc_bits: Option<u8>,
+    /// Analog bandwidth (in Hz) of the Rx path, or `None` for the sample rate
+    pub bandwidth: Option<f64>,
+    /// How the Rx's LO settles after a retune
+    pub lo_settling: LoSettling,
+    /// Interference the Rx picks up in addition to the transmitters
+    pub interferers: Vec<Interferer>,
+    /// The Rx's antennas
+    pub antennas: Antennas,
+    /// The links from each transmitter, indexed by the transmitter's index. `None` if this Rx
+    /// can't hear that transmitter
+    pub links: Vec<Option<LinkConfig>>,
+    /// The transmitter that leaks into this Rx, as part of the same full-duplex radio, and how
+    /// it leaks. `None` if the Rx is half-duplex
+    pub self_interference: Option<(usize, SelfInterference)>,
+    /// Changes to the Rx's parameters while the simulation runs, as (time in secs since the
+    /// start, change). Changes to the link apply to the links from every transmitter
+    pub timeline: Vec<(f64, ConfigChange)>,
+}
+
+/// Describes the nodes of a simulated network and the links between them. `build` then creates
+/// the simulated radios
+#[derive(Clone, Debug)]
+pub struct SimulatedNetwork {
+    /// Sample rate (in samples/sec) of all the radios
+    samp_rate: u64,
+    /// Frequency (in Hz) all the radios start at
+    start_freq: f64,
+    /// Transmitters added so far
+    txs: Vec<TxParams>,
+    /// Receivers added so far, along with their links
+    rxs: Vec<RxParams>,
+    /// If `Some`, the radios run in real time and can buffer this many seconds of samples
+    real_time: Option<f64>,
+}
+
+impl SimulatedNetwork {
+    /// A network with no radios, where every radio has sample rate `samp_rate` (in samples/sec)
+    /// and starts at frequency `start_freq` (in Hz)
+    pub fn new(samp_rate: u64, start_freq: f64) -> Result<Self, RadioError> {
+        validate_radio(samp_rate, start_freq)?;
+        Ok(Self {
+            samp_rate,
+            start_freq,
+            txs: Vec::new(),
+            rxs: Vec::new(),
+            real_time: None,
+        })
+    }
+
+    /// Run the radios in real time, i.e. paced by the wall clock from when they are built. Each
+    /// radio can buffer `buffer_secs` seconds of samples, beyond which receivers overflow and
+    /// transmitters block. `None` (the default) lets the radios run as fast as they are used
+    pub fn set_real_time(&mut self, buffer_secs: Option<f64>) -> Result<(), RadioError> {
+        validate_real_time(buffer_secs, self.samp_rate)?;
+        self.real_time = buffer_secs;
+        Ok(())
+    }
+
+    /// Add a transmitter and return its index. It can't be heard by any receiver until a link is
+    /// added
+    pub fn add_tx(&mut self) -> usize {
+        self.txs.push(TxParams::default());
+        self.txs.len() - 1
+    }
+
+    /// Add a receiver with the given noise power (in dB, relative to a unit power signal) and
+    /// return its index
+    pub fn add_rx(&mut self, noise_power: f64) -> Result<usize, RadioError> {
+        validate_noise_power(noise_power)?;
+        self.rxs.push(RxParams {
+            noise_power,
+            iq: IqImpairments::default(),
+            gain: 0.,
+            adc_bits: None,
+            bandwidth: None,
+            lo_settling: LoSettling::default(),
+            interferers: Vec::new(),
+            antennas: Antennas::default(),
+            links: Vec::new(),
+            self_interference: None,
+            timeline: Vec::new(),
+        });
+        Ok(self.rxs.len() - 1)
+    }
+
+    /// Set the DC offset and IQ imbalance of transmitter `tx`'s front end
+    pub fn set_tx_impairments(
+        &mut self,
+        tx: usize,
+        impairments: IqImpairments,
+    ) -> Result<(), RadioError> {
+        impairments.validate()?;
+        self.tx_mut(tx)?.iq = impairments;
+        Ok(())
+    }
+
+    /// Set the DC offset and IQ imbalance of receiver `rx`'s front end
+    pub fn set_rx_impairments(
+        &mut self,
+        rx: usize,
+        impairments: IqImpairments,
+    ) -> Result<(), RadioError> {
+        impairments.validate()?;
+        self.rx_mut(rx)?.iq = impairments;
+        Ok(())
+    }
+
+    /// Set the gain (in dB) transmitter `tx` starts with. It determines how hard the PA is driven,
+    /// and can be changed later with `SimulatedRadioTx::set_gain`
+    pub fn set_tx_gain(&mut self, tx: usize, gain: f64) -> Result<(), RadioError> {
+        validate_gain(gain)?;
+        self.tx_mut(tx)?.gain = gain;
+        Ok(())
+    }
+
+    /// Set the model of transmitter `tx`'s power amplifier
+    pub fn set_tx_pa(&mut self, tx: usize, pa: PaModel) -> Result<(), RadioError> {
+        pa.validate()?;
+        self.tx_mut(tx)?.pa = pa;
+        Ok(())
+    }
+
+    /// Set the gain (in dB) receiver `rx` starts with. It can be changed later with
+    /// `SimulatedRadioRx::set_gain`
+    pub fn set_rx_gain(&mut self, rx: usize, gain: f64) -> Result<(), RadioError> {
+        validate_gain(gain)?;
+        self.rx_mut(rx)?.gain = gain;
+        Ok(())
+    }
+
+    /// Give receiver `rx` an ADC with the given resolution, which clips the I and Q components of
+    /// samples to [-1, 1]. `None` means an ideal ADC
+    pub fn set_rx_adc_bits(&mut self, rx: usize, adc_bits: Option<u8>) -> Result<(), RadioError> {
+        validate_adc_bits(adc_bits)?;
+        self.rx_mut(rx)?.adc_bits = adc_bits;
+        Ok(())
+    }
+
+    /// Set the analog bandwidth (in Hz) of transmitter `tx`'s front end, which must be positive and
+    /// at most the sample rate. It can be changed later with `SimulatedRadioTx::set_bandwidth`
+    pub fn set_tx_bandwidth(&mut self, tx: usize, bandwidth: f64) -> Result<(), RadioError> {
+        validate_bandwidth(Some(bandwidth), self.samp_rate)?;
+        self.tx_mut(tx)?.bandwidth = Some(bandwidth);
+        Ok(())
+    }
+
+    /// Set the analog bandwidth (in Hz) of receiver `rx`'s front end, which must be positive and
+    /// at most the sample rate. It can be changed later with `SimulatedRadioRx::set_bandwidth`
+    pub fn set_rx_bandwidth(&mut self, rx: usize, bandwidth: f64) -> Result<(), RadioError> {
+        validate_bandwidth(Some(bandwidth), self.samp_rate)?;
+        self.rx_mut(rx)?.bandwidth = Some(bandwidth);
+        Ok(())
+    }
+
+    /// Set how receiver `rx`'s LO settles after `RadioRx::set_freq`
+    pub fn set_rx_lo_settling(
+        &mut self,
+        rx: usize,
+        settling: LoSettling,
+    ) -> Result<(), RadioError> {
+        settling.validate()?;
+        self.rx_mut(rx)?.lo_settling = settling;
+        Ok(())
+    }
+
+    /// Add interference to what receiver `rx` picks up
+    pub fn add_rx_interferer(
+        &mut self,
+        rx: usize,
+        interferer: Interferer,
+    ) -> Result<(), RadioError> {
+        interferer.validate(self.samp_rate)?;
+        self.rx_mut(rx)?.interferers.push(interferer);
+        Ok(())
+    }
+
+    /// Apply `change` to receiver `rx` `time` secs after the simulation starts, from the sample due
+    /// at that time. Changes to the link apply to the links from every transmitter
+    pub fn schedule_rx_change(
+        &mut self,
+        rx: usize,
+        time: f64,
+        change: ConfigChange,
+    ) -> Result<(), RadioError> {
+        let change = (time, change);
+        validate_timeline(std::slice::from_ref(&change), self.samp_rate)?;
+        self.rx_mut(rx)?.timeline.push(change);
+        Ok(())
+    }
+
+    /// Give transmitter `tx` several antennas, each of which is a channel of `RadioTxMulti`. The
+    /// MIMO channels of its links must fit
+    pub fn set_tx_antennas(&mut self, tx: usize, antennas: Antennas) -> Result<(), RadioError> {
+        antennas.validate()?;
+        self.tx_mut(tx)?;
+        for rx in &self.rxs {
+            if let Some(Some(link)) = rx.links.get(tx) {
+                link.mimo
+                    .validate_antennas(antennas.count, rx.antennas.count)?;
+            }
+        }
+        self.txs[tx].antennas = antennas;
+        Ok(())
+    }
+
+    /// Give receiver `rx` several antennas, each of which is a channel of `RadioRxMulti` with its
+    /// own noise. The MIMO channels of its links must fit
+    pub fn set_rx_antennas(&mut self, rx: usize, antennas: Antennas) -> Result<(), RadioError> {
+        antennas.validate()?;
+        self.rx_mut(rx)?;
+        for (link, tx) in self.rxs[rx].links.iter().zip(&self.txs) {
+            if let Some(link) = link {
+                link.mimo
+                    .validate_antennas(tx.antennas.count, antennas.count)?;
+            }
+        }
+        self.rxs[rx].antennas = antennas;
+        Ok(())
+    }
+
+    /// Make transmitter `tx` and receiver `rx` a full-duplex radio, so that `rx` hears `tx`
+    /// through `self_interference`. Any link from `tx` to `rx` is heard as well, so a full-duplex
+    /// radio usually has none. `None` makes them separate radios again
+    pub fn set_self_interference(
+        &mut self,
+        tx: usize,
+        rx: usize,
+        self_interference: Option<SelfInterference>,
+    ) -> Result<(), RadioError> {
+        self.tx_mut(tx)?;
+        if let Some(self_interference) = &self_interference {
+            self_interference.validate(self.samp_rate)?;
+        }
+        self.rx_mut(rx)?.self_interference = self_interference.map(|si| (tx, si));
+        Ok(())
+    }
+
+    fn tx_mut(&mut self, tx: usize) -> Result<&mut TxParams, RadioError> {
+        self.txs
+            .get_mut(tx)
+            .ok_or_else(|| RadioError::InvalidConfig(format!("No tx {}", tx)))
+    }
+
+    fn rx_mut(&mut self, rx: usize) -> Result<&mut RxParams, RadioError> {
+        self.rxs
+            .get_mut(rx)
+            .ok_or_else(|| RadioError::InvalidConfig(format!("No rx {}", rx)))
+    }
+
+    /// Let receiver `rx` hear transmitter `tx` through a link with the given parameters. Replaces
+    /// any existing link between them
+    pub fn add_link(&mut self, tx: usize, rx: usize, link: LinkConfig) -> Result<(), RadioError> {
+        if tx >= self.txs.len() || rx >= self.rxs.len() {
+            return Err(RadioError::InvalidConfig(format!(
+                "No link possible from tx {} to rx {}",
+                tx, rx
+            )));
+        }
+        link.validate(self.samp_rate)?;
+        link.mimo
+            .validate_antennas(self.txs[tx].antennas.count, self.rxs[rx].antennas.count)?;
+        let links = &mut self.rxs[rx].links;
+        if links.len() <= tx {
+            links.resize(tx + 1, None);
+        }
+        links[tx] = Some(link);
+        Ok(())
+    }
+
+    /// Create the transmitters and receivers, indexed as returned by `add_tx` and `add_rx`. Each
+    /// receiver gets its own RNG, seeded from `rng`. Every radio has its own device clock. Use
+    /// `SimulatedRadioTx::share_clock` to time a Tx's bursts by an Rx's clock
+    pub fn build<G: Rng>(
+        &self,
+        rng: &mut G,
+    ) -> (Vec<SimulatedRadioTx>, Vec<SimulatedRadioRx<StdRng>>) {
+        let rxs = self
+            .rxs
+            .iter()
+            .map(|params| {
+                let rx_rng = StdRng::from_rng(&mut *rng).expect("Could not seed the Rx's RNG");
+                (rx_rng, params.clone())
+            })
+            .collect();
+        connect(
+            self.samp_rate,
+            self.start_freq,
+            self.real_time,
+            &self.txs,
+            rxs,
+        )
+    }
+
+    /// Like `build`, but seeded with `seed`. For a given version of this crate, the same network
+    /// and seed produce bit-identical samples at every receiver, provided the same calls are made
+    /// to the radios, in the same order and from a single thread, and the network isn't paced in
+    /// real time. See `create_seeded_simulator`
+    pub fn build_seeded(
+        &self,
+        seed: u64,
+    ) -> (Vec<SimulatedRadioTx>, Vec<SimulatedRadioRx<StdRng>>) {
+        self.build(&mut StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Create one transmitter for each element of `txs` and one receiver for each element of `rxs`,
+/// which gives the receiver's RNG and parameters. Every radio gets its own device clock, which
+/// `SimulatedRadioTx::share_clock` can later share with an Rx on the same device. If
+/// `real_time` is `Some`, the radios are paced by the wall clock and buffer that many seconds of
+/// samples. The parameters must have been validated
+pub(super) fn connect<R: Rng>(
+    samp_rate: u64,
+    start_freq: f64,
+    real_time: Option<f64>,
+    txs: &[TxParams],
+    rxs: Vec<(R, RxParams)>,
+) -> (Vec<SimulatedRadioTx>, Vec<SimulatedRadioRx<R>>) {
+    let medium = Arc::new(Medium::new(samp_rate, real_time));
+    let tx_antennas: Vec<usize> = txs.iter().map(|params| params.antennas.count).collect();
+    let mut txs: Vec<SimulatedRadioTx> = txs
+        .iter()
+        .enumerate()
+        .map(|(id, params)| {
+            let clock = VirtualClock::new(samp_rate);
+            SimulatedRadioTx::new(id, params, start_freq, clock, medium.clone())
+        })
+        .collect();
+    let rxs = rxs
+        .into_iter()
+        .map(|(rng, params)| {
+            let (sender, receiver) = channel();
+            let leaking = params.self_interference.as_ref().map(|(tx, _)| *tx);
+            for (tx, tx_radio) in txs.iter_mut().enumerate() {
+                let linked = matches!(params.links.get(tx), Some(Some(_)));
+                if linked || leaking == Some(tx) {
+                    tx_radio.senders.push(sender.clone());
+                }
+            }
+            SimulatedRadioRx::new(
+                rng,
+                receiver,
+                medium.clone(),
+                &params,
+                &tx_antennas,
+                samp_rate,
+                start_freq,
+            )
+        })
+        .collect();
+    (txs, rxs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::MimoChannel;
+    use crate::{RadioRx, RadioTx, TxMetadata};
+    use num::{Complex, Zero};
+
+    /// (Tx, Rx, gain in dB, delay) of each link. Rx 0 hears both transmitters, and Rx 1 hears
+    /// only Tx 1
+    const LINKS: [(usize, usize, f64, u64); 3] = [(0, 0, -6., 3), (1, 0, 0., 10), (1, 1, -3., 5)];
+
+    /// A noiseless network of two transmitters and two receivers with `LINKS`
+    fn network() -> SimulatedNetwork {
+        let mut network = SimulatedNetwork::new(1_000_000, 2.4e9).unwrap();
+        for _ in 0..2 {
+            network.add_tx();
+            network.add_rx(f64::NEG_INFINITY).unwrap();
+        }
+        for (tx, rx, gain, delay) in LINKS {
+            let link = LinkConfig {
+                gain,
+                delay,
+                ..LinkConfig::default()
+            };
+            network.add_link(tx, rx, link).unwrap();
+        }
+        network
+    }
+
+    /// Indices of the samples louder than `level`
+    fn audible(samples: &[Complex<f32>], level: f32) -> Vec<usize> {
+        (0..samples.len())
+            .filter(|&i| samples[i].norm() > level)
+            .collect()
+    }
+
+    #[test]
+    fn receivers_hear_the_sum_of_their_links() {
+        let (mut txs, mut rxs) = network().build_seeded(0);
+        let bursts = [Complex::new(1., 0.), Complex::new(0., 0.5)];
+        for (tx, samp) in txs.iter_mut().zip(&bursts) {
+            tx.send(&[*samp; 100]).unwrap();
+        }
+        for (index, rx) in rxs.iter_mut().enumerate() {
+            let truth = rx.ground_truth();
+            let mut expected = vec![Complex::zero(); 200];
+            for (tx, link) in truth.links.iter().enumerate() {
+                let config = LINKS.iter().find(|l| (l.0, l.1) == (tx, index));
+                let (link, (_, _, gain, delay)) = match (link, config) {
+                    (Some(link), Some(config)) => (link, config),
+                    // An Rx without a link doesn't hear the Tx
+                    (None, None) => continue,
+                    _ => panic!("link from tx {} to rx {}", tx, index),
+                };
+                assert_eq!(link.delay, *delay);
+                // Each link starts with a random phase
+                let gain =
+                    Complex::from_polar(&10f32.powf(*gain as f32 / 20.), &(link.phase as f32));
+                for samp in &mut expected[*delay as usize..][..100] {
+                    *samp += bursts[tx] * gain;
+                }
+            }
+            let samples = rx.recv(200).unwrap().0;
+            for (samp, expected) in samples.iter().zip(&expected) {
+                assert!((samp - expected).norm() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_clock_times_bursts() {
+        let (mut txs, mut rxs) = network().build_seeded(0);
+        rxs[1].set_time_now(5.);
+        // Tx 1 follows Rx 1's clock. Tx 0 keeps its own, which is 5 secs behind, so its burst is
+        // too far in the future to be heard
+        txs[1].share_clock(&rxs[1]);
+        let md = TxMetadata {
+            time: Some(5. + 100e-6),
+            start_of_burst: true,
+            end_of_burst: true,
+        };
+        for tx in &mut txs {
+            tx.send_burst(&[Complex::new(1., 0.); 10], &md).unwrap();
+        }
+        // The burst starts 100 samples in, after each link's delay
+        let (samples, time) = rxs[1].recv(200).unwrap();
+        assert_eq!(time, 5_000_000);
+        assert_eq!(audible(samples, 0.5), (105..115).collect::<Vec<_>>());
+        let (samples, time) = rxs[0].recv(200).unwrap();
+        assert_eq!(time, 0);
+        assert_eq!(audible(samples, 0.), (110..120).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn links_must_fit_the_network() {
+        let mut network = network();
+        assert!(network.add_link(2, 0, LinkConfig::default()).is_err());
+        assert!(network.add_link(0, 2, LinkConfig::default()).is_err());
+
+        // The MIMO matrix must have a row per Rx antenna and a column per Tx antenna
+        let antennas = |count| Antennas {
+            count,
+            ..Antennas::default()
+        };
+        network.set_tx_antennas(0, antennas(2)).unwrap();
+        let mimo = |rows, cols| LinkConfig {
+            mimo: MimoChannel::Matrix(vec![vec![Complex::new(1., 0.); cols]; rows]),
+            ..LinkConfig::default()
+        };
+        assert!(network.add_link(0, 1, mimo(2, 1)).is_err());
+        assert!(network.add_link(0, 1, mimo(1, 1)).is_err());
+        network.add_link(0, 1, mimo(1, 2)).unwrap();
+        // Nor can the antennas change so that it no longer fits
+        assert!(network.set_tx_antennas(0, antennas(1)).is_err());
+        assert!(network.set_rx_antennas(1, antennas(2)).is_err());
+    }
+}
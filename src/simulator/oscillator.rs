@@ -0,0 +1,106 @@
+//! The local oscillator of a simulated Rx. Real LOs take a while to lock after they are retuned,
+//! during which the frequency is off and the `lo_locked` sensor reads false, and they lock with an
+//! arbitrary phase.
+
+use crate::RadioError;
+use num::Complex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// How a radio's local oscillator settles after it is retuned. By default, it settles instantly
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoSettling {
+    /// Time (in secs) the LO takes to lock after a retune. Until then, the `lo_locked` sensor
+    /// reads false. If positive, the LO also locks with a random phase
+    pub settle_time: f64,
+    /// Largest error (in Hz) in the LO's frequency just after a retune. The error is drawn
+    /// uniformly from [-max_freq_error, max_freq_error] and decays exponentially, to under 1% by
+    /// `settle_time`
+    pub max_freq_error: f64,
+}
+
+impl LoSettling {
+    /// Check that the parameters make sense
+    pub(super) fn validate(&self) -> Result<(), RadioError> {
+        let invalid = |msg: &str| Err(RadioError::InvalidConfig(msg.to_string()));
+        if !(self.settle_time.is_finite() && self.settle_time >= 0.) {
+            return invalid("settle_time must be finite and non-negative");
+        }
+        if !(self.max_freq_error.is_finite() && self.max_freq_error >= 0.) {
+            return invalid("max_freq_error must be finite and non-negative");
+        }
+        Ok(())
+    }
+}
+
+/// Applies the phase of the LO, which goes through a transient after every retune
+pub(super) struct Oscillator {
+    /// Settling time in samples
+    settle_len: u64,
+    /// Time constant (in samples) of the frequency error's decay
+    time_constant: f64,
+    max_freq_error: f64,
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// Index of the sample at which the LO was last retuned, the frequency error then (in
+    /// radians/sample) and the phase (in radians) it started with. `None` if it was never retuned
+    /// or settles instantly
+    retune: Option<(u64, f64, f64)>,
+}
+
+impl Oscillator {
+    /// `settling` must have been validated
+    pub fn new(settling: &LoSettling, samp_rate: u64) -> Self {
+        let settle_len = (settling.settle_time * samp_rate as f64).round() as u64;
+        Self {
+            settle_len,
+            // e^-5 < 1%
+            time_constant: settle_len as f64 / 5.,
+            max_freq_error: settling.max_freq_error,
+            samp_rate: samp_rate as f64,
+            retune: None,
+        }
+    }
+
+    /// The LO is retuned at the `index`^th sample
+    pub fn retune<R: Rng>(&mut self, index: u64, rng: &mut R) {
+        if self.settle_len == 0 {
+            return;
+        }
+        let error = (2. * rng.gen::<f64>() - 1.) * self.max_freq_error;
+        let phase = rng.gen::<f64>() * 2. * PI;
+        self.retune = Some((index, 2. * PI * error / self.samp_rate, phase));
+    }
+
+    /// Whether the LO has locked by the `index`^th sample
+    pub fn locked(&self, index: u64) -> bool {
+        match self.retune {
+            Some((start, _, _)) => index >= start + self.settle_len,
+            None => true,
+        }
+    }
+
+    /// Apply the LO's phase to `block`, whose first sample is the `index`^th
+    pub fn process_block(&self, index: u64, block: &mut [Complex<f32>]) {
+        let (start, error, phase) = match self.retune {
+            Some(retune) => retune,
+            None => return,
+        };
+        let tau = self.time_constant;
+        // The phase the frequency error has accumulated `t` samples after the retune
+        let drift = |t: f64| error * tau * (1. - (-t / tau).exp());
+        // Number of samples in the block before the LO locks
+        let transient = ((start + self.settle_len).saturating_sub(index) as usize).min(block.len());
+        for (i, samp) in block[..transient].iter_mut().enumerate() {
+            let t = (index + i as u64 - start) as f64;
+            *samp *= Complex::from_polar(&1., &((phase + drift(t)) as f32));
+        }
+        // Once locked, the phase stays where the transient left it
+        let rotation = Complex::from_polar(&1., &((phase + drift(self.settle_len as f64)) as f32));
+        block[transient..]
+            .iter_mut()
+            .for_each(|samp| *samp *= rotation);
+    }
+}
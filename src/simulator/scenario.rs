@@ -0,0 +1,304 @@
+//! Scenario files, which describe a simulation in TOML or JSON so that experiments can be shared
+//! and rerun without recompiling. A scenario is a serialized `RadioSimulatorConfig`: parameters it
+//! leaves out keep their defaults, and its timeline changes some of them as the simulation runs,
+//! e.g. raising the noise after 2 secs and adding multipath after 5. For example
+//!
+//! ```toml
+//! samp_rate = 2000000
+//! noise_power = -30.0
+//! timeline = [
+//!     [2.0, { NoisePower = -10.0 }],
+//!     [5.0, { Channel = { Static = [[1e-6, [0.5, 0.0]]] } }],
+//! ]
+//!
+//! [link]
+//! max_cfo = 1000.0
+//! ```
+
+use super::channel::ChannelModel;
+use super::config::{validate_gain, validate_noise_power, LinkConfig, RadioSimulatorConfig};
+use super::interference::Interferer;
+use crate::RadioError;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A change to the parameters of a running simulation. It applies from the sample it is due at,
+/// even in the middle of the samples returned by a call to `recv`. Changes to links apply to the
+/// links from every Tx, but not to a radio's self-interference path, whose leakage is set by
+/// `SelfInterference` alone
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ConfigChange {
+    /// Set the Rx's noise power (in dB)
+    NoisePower(#[serde(with = "noise_power")] f64),
+    /// Set the Rx's gain (in dB), like `SimulatedRadioRx::set_gain`
+    RxGain(f64),
+    /// Set the link's gain (in dB), which replaces `LinkConfig::gain`
+    LinkGain(f64),
+    /// Replace the link's multipath channel. The new channel starts out silent, so it has no
+    /// echoes of the samples received before the change
+    Channel(ChannelModel),
+    /// Add interference to what the Rx picks up
+    AddInterferer(Interferer),
+}
+
+impl ConfigChange {
+    /// Check that the change makes sense for radios with the given sample rate
+    fn validate(&self, samp_rate: u64) -> Result<(), RadioError> {
+        match self {
+            ConfigChange::NoisePower(noise_power) => validate_noise_power(*noise_power),
+            ConfigChange::RxGain(gain) => validate_gain(*gain),
+            ConfigChange::LinkGain(gain) => LinkConfig {
+                gain: *gain,
+                ..LinkConfig::default()
+            }
+            .validate(samp_rate),
+            ConfigChange::Channel(channel) => LinkConfig {
+                channel: channel.clone(),
+                ..LinkConfig::default()
+            }
+            .validate(samp_rate),
+            ConfigChange::AddInterferer(interferer) => interferer.validate(samp_rate),
+        }
+    }
+}
+
+/// Check the (time in secs, change) pairs of a timeline
+pub(super) fn validate_timeline(
+    timeline: &[(f64, ConfigChange)],
+    samp_rate: u64,
+) -> Result<(), RadioError> {
+    for (time, change) in timeline {
+        if !(time.is_finite() && *time >= 0.) {
+            return Err(RadioError::InvalidConfig(
+                "timeline times must be finite and non-negative".to_string(),
+            ));
+        }
+        change.validate(samp_rate)?;
+    }
+    Ok(())
+}
+
+/// (De)serializes a noise power in dB, which is `-inf` if there is no noise. JSON has no infinity,
+/// so it is written as the string `"-inf"`. Either that or a number is read back
+pub(super) mod noise_power {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    const NO_NOISE: &str = "-inf";
+
+    pub fn serialize<S: Serializer>(noise_power: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if *noise_power == f64::NEG_INFINITY {
+            serializer.serialize_str(NO_NOISE)
+        } else {
+            serializer.serialize_f64(*noise_power)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        deserializer.deserialize_any(NoisePowerVisitor)
+    }
+
+    struct NoisePowerVisitor;
+
+    impl<'de> Visitor<'de> for NoisePowerVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a noise power in dB, or \"{}\" for no noise", NO_NOISE)
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+            Ok(value as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+            match value {
+                NO_NOISE => Ok(f64::NEG_INFINITY),
+                _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+            }
+        }
+    }
+}
+
+/// Format of a scenario file
+enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// The format of the file at `path`, from its extension
+    fn of(path: &Path) -> Result<Self, RadioError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(Format::Toml),
+            Some("json") => Ok(Format::Json),
+            _ => Err(RadioError::InvalidConfig(format!(
+                "{} is not a .toml or .json scenario file",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl RadioSimulatorConfig {
+    /// Parse a scenario in TOML. It is checked like `RadioSimulatorConfigBuilder::build`
+    pub fn from_toml(scenario: &str) -> Result<Self, RadioError> {
+        toml::from_str(scenario).map_err(|err| invalid_scenario(&err))
+    }
+
+    /// Parse a scenario in JSON. It is checked like `RadioSimulatorConfigBuilder::build`
+    pub fn from_json(scenario: &str) -> Result<Self, RadioError> {
+        serde_json::from_str(scenario).map_err(|err| invalid_scenario(&err))
+    }
+
+    /// The config as a TOML scenario
+    pub fn to_toml(&self) -> Result<String, RadioError> {
+        toml::to_string(self).map_err(|err| invalid_scenario(&err))
+    }
+
+    /// The config as a JSON scenario
+    pub fn to_json(&self) -> Result<String, RadioError> {
+        serde_json::to_string_pretty(self).map_err(|err| invalid_scenario(&err))
+    }
+
+    /// Load a scenario file, which is in TOML or JSON depending on whether its extension is
+    /// `.toml` or `.json`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RadioError> {
+        let path = path.as_ref();
+        let format = Format::of(path)?;
+        let scenario = std::fs::read_to_string(path).map_err(|err| {
+            RadioError::InvalidConfig(format!("could not read {}: {}", path.display(), err))
+        })?;
+        match format {
+            Format::Toml => Self::from_toml(&scenario),
+            Format::Json => Self::from_json(&scenario),
+        }
+    }
+
+    /// Save the config to a scenario file, in TOML or JSON depending on whether its extension is
+    /// `.toml` or `.json`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), RadioError> {
+        let path = path.as_ref();
+        let scenario = match Format::of(path)? {
+            Format::Toml => self.to_toml()?,
+            Format::Json => self.to_json()?,
+        };
+        std::fs::write(path, scenario).map_err(|err| {
+            RadioError::InvalidConfig(format!("could not write {}: {}", path.display(), err))
+        })
+    }
+}
+
+fn invalid_scenario(err: &dyn std::fmt::Display) -> RadioError {
+    RadioError::InvalidConfig(format!("invalid scenario: {}", err.to_string().trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::{create_seeded_simulator, Interferer, LoSettling};
+    use crate::RadioRx;
+    use num::{Complex, Zero};
+
+    /// A config that sets something in most of its sub-configs, and has a timeline
+    fn config(noise_power: f64) -> RadioSimulatorConfig {
+        RadioSimulatorConfig::builder()
+            .samp_rate(2_000_000)
+            .noise_power(noise_power)
+            .max_cfo(1e3)
+            .add_multipath(1e-6, Complex::new(0.5, -0.1))
+            .add_interferer(Interferer::Tone {
+                power: -30.,
+                offset: 1e4,
+            })
+            .lo_settling(LoSettling {
+                settle_time: 1e-3,
+                max_freq_error: 100.,
+            })
+            .at(0.5, ConfigChange::NoisePower(f64::NEG_INFINITY))
+            .at(1., ConfigChange::RxGain(10.))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        for noise_power in [-20., f64::NEG_INFINITY] {
+            let config = config(noise_power);
+            let toml = config.to_toml().unwrap();
+            assert_eq!(RadioSimulatorConfig::from_toml(&toml).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for noise_power in [-20., f64::NEG_INFINITY] {
+            let config = config(noise_power);
+            let json = config.to_json().unwrap();
+            assert_eq!(RadioSimulatorConfig::from_json(&json).unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let config = config(f64::NEG_INFINITY);
+        for ext in ["toml", "json"] {
+            let path =
+                std::env::temp_dir().join(format!("usrp_scenario_{}.{}", std::process::id(), ext));
+            config.save(&path).unwrap();
+            let loaded = RadioSimulatorConfig::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.unwrap(), config);
+        }
+    }
+
+    #[test]
+    fn missing_parameters_keep_defaults() {
+        let config = RadioSimulatorConfig::from_json(r#"{"noise_power": "-inf"}"#).unwrap();
+        let expected = RadioSimulatorConfig::builder()
+            .noise_power(f64::NEG_INFINITY)
+            .build()
+            .unwrap();
+        assert_eq!(config, expected);
+        let config = RadioSimulatorConfig::from_toml("noise_power = -inf").unwrap();
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn invalid_scenarios_are_rejected() {
+        for toml in [
+            "adc_bits = 40",
+            "noise_power = \"loud\"",
+            "timeline = [[-1.0, { RxGain = 0.0 }]]",
+            "misspelled = 1",
+        ] {
+            assert!(RadioSimulatorConfig::from_toml(toml).is_err(), "{}", toml);
+        }
+        assert!(RadioSimulatorConfig::load("scenario.yaml").is_err());
+    }
+
+    #[test]
+    fn changes_apply_from_their_sample() {
+        // The noise stops 100 samples into the first call to `recv`
+        let config = RadioSimulatorConfig::builder()
+            .samp_rate(1_000_000)
+            .noise_power(-20.)
+            .at(100e-6, ConfigChange::NoisePower(f64::NEG_INFINITY))
+            .build()
+            .unwrap();
+        let (_tx, mut rx) = create_seeded_simulator(&config, 0);
+        let (samples, _) = rx.recv(256).unwrap();
+        assert_eq!(samples.len(), 256);
+        assert!(samples[..100].iter().all(|samp| !samp.is_zero()));
+        assert!(samples[100..].iter().all(|samp| samp.is_zero()));
+    }
+}
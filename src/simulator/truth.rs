@@ -0,0 +1,50 @@
+//! The true state of a simulation, which a real receiver would have to estimate. Tests can compare
+//! a synchronizer's estimates against it.
+
+use num::Complex;
+
+/// Snapshot of the state of a `SimulatedRadioRx`, as of the next sample it will receive
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroundTruth {
+    /// Number of samples received so far
+    pub tot_num_samps: u64,
+    /// Power (in dB) of the Rx's noise, relative to a unit power signal
+    pub noise_power: f64,
+    /// Gain (in dB) of the Rx
+    pub gain: f64,
+    /// Whether the Rx's LO has locked since it was last retuned
+    pub lo_locked: bool,
+    /// The link from each Tx, indexed by the Tx's index. `None` if the Rx can't hear that Tx
+    pub links: Vec<Option<LinkTruth>>,
+    /// The path through which the Rx hears its own Tx, if it is part of a full-duplex radio. Its
+    /// `cfo` and `sfo` are always 0, and its first tap is the direct leakage
+    pub self_interference: Option<LinkTruth>,
+}
+
+/// Snapshot of the state of the link from one Tx to the Rx
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkTruth {
+    /// Number of Rx samples before the Tx's first sample reaches the Rx, i.e. the start time
+    /// offset. If the nodes move, this includes the current propagation delay, and if the radios
+    /// are tuned differently, the delay of the filter that selects the band the Rx hears
+    pub delay: u64,
+    /// The current CFO in Hz, including any due to the sample clock offset, the Doppler shift of
+    /// moving nodes or the difference between the radios' frequencies
+    pub cfo: f64,
+    /// Phase (in radians) the CFO and phase noise will have accumulated by the next sample, which
+    /// is applied to it. Phase noise and CFO drift may still move it by that sample's random
+    /// step. It excludes the phase of the multipath taps
+    pub phase: f64,
+    /// The current offset (in ppm) of the Rx's sample clock relative to the Tx's
+    pub sfo: f64,
+    /// Instantaneous SNR (in dB) of a unit power signal from the Tx, accounting for the link's gain
+    /// and the current power of the multipath channel
+    pub snr: f64,
+    /// Delay (in secs) and current gain of every multipath tap, excluding the link's gain. If the
+    /// Tx has several antennas, these are the taps from its first antenna
+    pub taps: Vec<(f64, Complex<f32>)>,
+    /// Current gain from every Tx antenna to every Rx antenna, on top of the rest of the link:
+    /// `mimo[i][j]` is the gain from Tx antenna `j` to Rx antenna `i`. It excludes the phase
+    /// offsets of the antennas' LOs, and isn't included in `snr`
+    pub mimo: Vec<Vec<Complex<f32>>>,
+}
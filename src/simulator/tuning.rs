@@ -0,0 +1,166 @@
+//! Frequency translation between a Tx and an Rx tuned to different centre frequencies. The Tx's
+//! signal is shifted by the difference, and only the part of it that falls within both radios'
+//! analog bandwidths (and the Rx's sample rate) gets through, like the anti-aliasing filters of
+//! real front ends.
+
+use num::{Complex, Zero};
+use std::f64::consts::PI;
+
+/// Number of taps of the band pass filter. Its group delay is half of one less than this
+const FILTER_LEN: usize = 33;
+
+/// Centre frequency and analog bandwidth of a radio, both in Hz
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Tuning {
+    pub freq: f64,
+    pub bandwidth: f64,
+}
+
+/// How the signal of a Tx with a given tuning reaches the Rx
+#[derive(Clone, Debug)]
+enum Path {
+    /// The radios are tuned alike and the whole band gets through
+    Direct,
+    /// The part of the signal in the band that gets through is selected by a band pass filter
+    /// with these taps (in reverse order), and shifted up by this frequency (in Hz)
+    Filtered(Vec<Complex<f32>>, f64),
+    /// Nothing of the Tx's band gets through
+    Blocked,
+}
+
+/// Translates the signal of a Tx, whose tuning may change over time, to an Rx's tuning
+pub(super) struct Translator {
+    /// Sample rate in samples/sec
+    samp_rate: f64,
+    /// The tunings of the Tx and Rx for which `path` was designed
+    tunings: Option<(Tuning, Tuning)>,
+    path: Path,
+    /// The last `FILTER_LEN - 1` samples of the Tx's signal, which the filter needs at the start
+    /// of the next block
+    history: Vec<Complex<f32>>,
+    /// Scratch space for the filter's input
+    input: Vec<Complex<f32>>,
+}
+
+impl Translator {
+    pub fn new(samp_rate: u64) -> Self {
+        Self {
+            samp_rate: samp_rate as f64,
+            tunings: None,
+            path: Path::Direct,
+            history: vec![Complex::zero(); FILTER_LEN - 1],
+            input: Vec::new(),
+        }
+    }
+
+    /// Translate `signal`, whose first sample is the Rx's `rx_index`^th, in place. `segments`
+    /// gives the Tx's tuning from each position in `signal` onwards, and must start at 0
+    pub fn process(
+        &mut self,
+        signal: &mut [Complex<f32>],
+        rx_index: u64,
+        segments: &[(usize, Tuning)],
+        rx: Tuning,
+    ) {
+        for (i, (start, tx)) in segments.iter().enumerate() {
+            let end = segments.get(i + 1).map_or(signal.len(), |(end, _)| *end);
+            if self.tunings != Some((*tx, rx)) {
+                self.path = self.design(*tx, rx);
+                self.tunings = Some((*tx, rx));
+            }
+            self.process_segment(&mut signal[*start..end], rx_index + *start as u64);
+        }
+    }
+
+    /// Extra delay (in samples) and frequency shift (in Hz) of the last sample processed
+    pub fn offsets(&self) -> (u64, f64) {
+        match &self.path {
+            Path::Filtered(_, shift) => ((FILTER_LEN as u64 - 1) / 2, *shift),
+            _ => (0, 0.),
+        }
+    }
+
+    fn process_segment(&mut self, signal: &mut [Complex<f32>], rx_index: u64) {
+        let keep = FILTER_LEN - 1;
+        match &self.path {
+            Path::Direct => remember(&mut self.history, signal),
+            Path::Blocked => {
+                remember(&mut self.history, signal);
+                signal.iter_mut().for_each(|samp| *samp = Complex::zero());
+            }
+            Path::Filtered(taps, shift) => {
+                self.input.clear();
+                self.input.extend_from_slice(&self.history);
+                self.input.extend_from_slice(signal);
+                // Rotate a phasor in double precision, starting from the phase at `rx_index`, so
+                // the shift doesn't depend on how the signal was split into blocks
+                let cycles = shift / self.samp_rate;
+                let start = (cycles * rx_index as f64).fract();
+                let mut phasor = Complex::from_polar(&1., &(2. * PI * start));
+                let rotation = Complex::from_polar(&1., &(2. * PI * cycles));
+                for (samp, window) in signal.iter_mut().zip(self.input.windows(FILTER_LEN)) {
+                    let filtered: Complex<f32> = taps.iter().zip(window).map(|(t, x)| t * x).sum();
+                    *samp = filtered * Complex::new(phasor.re as f32, phasor.im as f32);
+                    phasor *= rotation;
+                }
+                let len = self.input.len();
+                self.history.copy_from_slice(&self.input[len - keep..]);
+            }
+        }
+    }
+
+    /// How the signal of a Tx tuned to `tx` reaches an Rx tuned to `rx`
+    fn design(&self, tx: Tuning, rx: Tuning) -> Path {
+        let nyquist = self.samp_rate / 2.;
+        let shift = tx.freq - rx.freq;
+        // The band that gets through, relative to the Tx's centre frequency
+        let low = (-nyquist)
+            .max(-tx.bandwidth / 2.)
+            .max(-shift - rx.bandwidth / 2.);
+        let high = nyquist
+            .min(tx.bandwidth / 2.)
+            .min(-shift + rx.bandwidth / 2.);
+        if high <= low {
+            return Path::Blocked;
+        }
+        if shift == 0. && low <= -nyquist && high >= nyquist {
+            return Path::Direct;
+        }
+
+        // A windowed sinc low pass filter, moved up to the centre of the band
+        let centre = (low + high) / 2. / self.samp_rate;
+        let cutoff = (high - low) / 2. / self.samp_rate;
+        let mid = (FILTER_LEN - 1) as f64 / 2.;
+        let mut dc_gain = 0.;
+        let mut taps: Vec<Complex<f64>> = (0..FILTER_LEN)
+            .map(|k| {
+                let t = k as f64 - mid;
+                let sinc = if t == 0. {
+                    2. * cutoff
+                } else {
+                    (2. * PI * cutoff * t).sin() / (PI * t)
+                };
+                let window = 0.54 - 0.46 * (2. * PI * k as f64 / (FILTER_LEN - 1) as f64).cos();
+                dc_gain += sinc * window;
+                Complex::from_polar(&(sinc * window), &(2. * PI * centre * t))
+            })
+            .collect();
+        taps.reverse();
+        let taps = taps
+            .iter()
+            .map(|t| Complex::new((t.re / dc_gain) as f32, (t.im / dc_gain) as f32))
+            .collect();
+        Path::Filtered(taps, shift)
+    }
+}
+
+/// Keep the end of `signal` in `history`, for the filter
+fn remember(history: &mut Vec<Complex<f32>>, signal: &[Complex<f32>]) {
+    let keep = history.len();
+    if signal.len() >= keep {
+        history.copy_from_slice(&signal[signal.len() - keep..]);
+    } else {
+        history.drain(..signal.len());
+        history.extend_from_slice(signal);
+    }
+}
@@ -1,8 +1,7 @@
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
-use crate::RadioRx;
+use crate::{RadioError, RadioRx, RadioRxMulti, RadioTx, RadioTxMulti, TxMetadata};
 
-use failure::{format_err, Error};
 use num::complex::Complex;
 
 use std::ffi::CString;
@@ -11,14 +10,51 @@ use std::ffi::CString;
 /// number of samples per second, `freq` is the requested center frequency in Hz, `gain` is the
 /// gain in dB (uncaliberated units), and `bw` is the analog bandwidth of the receiver in Hz, `tx`
 /// tells us whether to configure this as a transmitter or a receiver
-fn new_generic(args: &str, rate: u64, freq: f64, gain: f64, bw: f64, tx: bool) -> *mut MultiUsrp {
+fn new_generic(
+    args: &str,
+    rate: u64,
+    freq: f64,
+    gain: f64,
+    bw: f64,
+    tx: bool,
+) -> Result<*mut MultiUsrp, RadioError> {
     // So that CString is not deallocated too early
     let args_ptr = CString::new(args).unwrap().into_raw();
-    unsafe {
+    let res = unsafe {
         let res = new_usrp(args_ptr, rate as f64, freq, gain, bw, tx);
         // So that it is deallocated and doesn't leak memory
         let _tmp = CString::from_raw(args_ptr);
-        return res;
+        res
+    };
+    // The wrapper returns null if it couldn't create the device
+    if res.is_null() {
+        return Err(RadioError::DeviceNotFound);
+    }
+    Ok(res)
+}
+
+/// Interpret the (negative) error code returned by the wrapper's `recv`
+fn rx_error(code: i64) -> RadioError {
+    match code {
+        -3 => RadioError::ChannelMismatch,
+        -4 => RadioError::Timeout,
+        -5 => RadioError::LateCommand,
+        -6 => RadioError::BrokenChain,
+        -7 => RadioError::Overflow,
+        -8 => RadioError::Alignment,
+        -9 => RadioError::BadPacket,
+        -12 => RadioError::MissingTimeSpec,
+        -13 => RadioError::OutOfSequence,
+        _ => RadioError::Unknown(code),
+    }
+}
+
+/// Interpret the (negative) error code returned by the wrapper's `send`
+fn tx_error(code: i32) -> RadioError {
+    match code {
+        -2 => RadioError::ChannelMismatch,
+        -3 => RadioError::Timeout,
+        _ => RadioError::Unknown(code as i64),
     }
 }
 
@@ -40,7 +76,7 @@ fn set_clock_source_wrapper(
     usrp: *mut MultiUsrp,
     clk_src: ClockSource,
     mboard: usize,
-) -> Result<(), Error> {
+) -> Result<(), RadioError> {
     let code = match clk_src {
         ClockSource::Internal => 0,
         ClockSource::Mimo => 1,
@@ -49,10 +85,7 @@ fn set_clock_source_wrapper(
     };
     let err_code = unsafe { set_clock_source(usrp, code, mboard) };
     if err_code < 0 {
-        return Err(format_err!(
-            "Error in setting clock source of tx: {}",
-            err_code
-        ));
+        return Err(RadioError::Unknown(err_code as i64));
     }
     Ok(())
 }
@@ -67,8 +100,8 @@ pub fn new_tx_usrp(
     gain: f64,
     bw: f64,
     clk_src: ClockSource,
-) -> Result<UsrpTxSingleStream, Error> {
-    let usrp = new_generic(args, rate, freq, gain, bw, true);
+) -> Result<UsrpTxSingleStream, RadioError> {
+    let usrp = new_generic(args, rate, freq, gain, bw, true)?;
     set_clock_source_wrapper(usrp, clk_src, 0)?;
     Ok(UsrpTxSingleStream {
         usrp,
@@ -80,7 +113,8 @@ pub fn new_tx_usrp(
 /// A new Rx USRP. `args` gives the address of the USRP, `rate` is the number of samples per
 /// second, `freq` is the requested center frequency in Hz, `gain` is the gain in dB
 /// (uncaliberated units), and `bw` is the analog bandwidth of the receiver in Hz. If `print_samps`
-/// is true, prints every n^th sample
+/// is true, prints every n^th sample of the first channel. Samples are received from every channel
+/// the device provides (see `RadioRxMulti`)
 pub fn new_rx_usrp(
     args: &str,
     rate: u64,
@@ -89,20 +123,22 @@ pub fn new_rx_usrp(
     bw: f64,
     print_samples: Option<usize>,
     clk_src: ClockSource,
-) -> Result<UsrpRxSingleStream, Error> {
-    let usrp = new_generic(args, rate, freq, gain, bw, false);
+) -> Result<UsrpRxSingleStream, RadioError> {
+    let usrp = new_generic(args, rate, freq, gain, bw, false)?;
     set_clock_source_wrapper(usrp, clk_src, 0)?;
+    let num_channels = unsafe { get_rx_num_channels(usrp) };
     Ok(UsrpRxSingleStream {
         usrp,
         print_samples,
         streamer: None,
         buf: Vec::new(),
         tot_num_samps: 0,
-        ret_buf: Vec::new(),
+        ret_bufs: vec![Vec::new(); num_channels],
     })
 }
 
-/// A single channel receive usrp streamer
+/// A receive usrp streamer. Despite the name, it receives from all the channels of the device.
+/// `RadioRx::recv` returns only the first one, while `RadioRxMulti::recv_multi` returns all of them
 pub struct UsrpRxSingleStream {
     usrp: *mut MultiUsrp,
     /// If `Some`, print one out of every `n` samples
@@ -113,8 +149,8 @@ pub struct UsrpRxSingleStream {
     buf: Vec<f32>,
     /// Total number of samples returned so far
     tot_num_samps: u64,
-    /// We return pointers to this buffer to give data back to the caller
-    ret_buf: Vec<Complex<f32>>,
+    /// We return pointers to these buffers (one per channel) to give data back to the caller
+    ret_bufs: Vec<Vec<Complex<f32>>>,
 }
 
 /// A single channel transmit usrp streamer
@@ -152,11 +188,43 @@ impl RadioRx for UsrpRxSingleStream {
         }
     }
 
-    /// Receive at-most `len` samples from the USRP. Returns the exactly `len` samples, the
-    /// timestamp (in microseconds) of the first sample
-    fn recv<'a>(&'a mut self, len: usize) -> Result<(&'a [Complex<f32>], u64), Error> {
+    /// Receive `len` samples from the first channel of the USRP. Returns the exactly `len` samples,
+    /// the timestamp (in microseconds) of the first sample
+    fn recv(&mut self, len: usize) -> Result<(&[Complex<f32>], u64), RadioError> {
+        let (bufs, time_spec) = self.recv_multi(len)?;
+        Ok((&bufs[0], time_spec))
+    }
+
+    fn tot_num_samps(&self) -> u64 {
+        self.tot_num_samps
+    }
+
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError> {
+        unsafe { set_rx_freq(self.usrp, freq); }
+        Ok(())
+    }
+
+    /// Reads the "lo_locked" sensor of the first channel
+    fn lo_locked(&mut self) -> Result<bool, RadioError> {
+        let code = unsafe { get_rx_lo_locked(self.usrp, 0) };
+        if code < 0 {
+            return Err(RadioError::Unknown(code as i64));
+        }
+        Ok(code == 1)
+    }
+}
+
+impl RadioRxMulti for UsrpRxSingleStream {
+    fn num_channels(&self) -> usize {
+        self.ret_bufs.len()
+    }
+
+    /// Receive `len` samples from every channel of the USRP. Returns exactly `len` samples per
+    /// channel and the timestamp (in microseconds) of the first sample
+    fn recv_multi(&mut self, len: usize) -> Result<(&[Vec<Complex<f32>>], u64), RadioError> {
+        let num_channels = self.ret_bufs.len();
         // TODO: This additional copy is no longer required as the C wrapper itself does one copy
-        self.buf.resize(len * 2, 0.);
+        self.buf.resize(len * 2 * num_channels, 0.);
 
         // If streamer doesn't exist, create it now
         if self.streamer.is_none() {
@@ -166,26 +234,27 @@ impl RadioRx for UsrpRxSingleStream {
         // Call the C-wrapper function
         let returned: i64 = unsafe {
             let buf_arr: *mut f32 = self.buf.as_mut_ptr();
-            recv(self.streamer.unwrap(), buf_arr, len, 1)
+            recv(self.streamer.unwrap(), buf_arr, len, num_channels)
         };
 
         // See if we had an error
         if returned < 0 {
-            return Err(format_err!("Error in receiving. Got code: {}", returned));
+            return Err(rx_error(returned));
         }
         let time_spec = returned as u64;
 
-        // Copy data into a Complex<f32> array
-        self.ret_buf.resize(len, Complex::new(0., 0.));
-        self.ret_buf.clear();
-        for i in 0..len {
-            self.ret_buf
-                .push(Complex::new(self.buf[2 * i], self.buf[2 * i + 1]));
+        // Copy data into Complex<f32> arrays. The wrapper places the channels one after the other
+        for (chan, ret_buf) in self.ret_bufs.iter_mut().enumerate() {
+            let chan_buf = &self.buf[2 * len * chan..2 * len * (chan + 1)];
+            ret_buf.clear();
+            for i in 0..len {
+                ret_buf.push(Complex::new(chan_buf[2 * i], chan_buf[2 * i + 1]));
+            }
         }
 
         if let Some(n) = self.print_samples {
             let mut t = self.tot_num_samps;
-            for x in &self.ret_buf {
+            for x in &self.ret_bufs[0] {
                 if t % n as u64 == 0 {
                     println!("Sample: {} {}", x.norm(), x.arg());
                 }
@@ -196,23 +265,13 @@ impl RadioRx for UsrpRxSingleStream {
         // Keep track of the number of samples returned so far
         self.tot_num_samps += len as u64;
 
-        Ok((&self.ret_buf, time_spec))
-    }
-
-    fn tot_num_samps(&self) -> u64 {
-        self.tot_num_samps
-    }
-
-    fn set_freq(&mut self, freq: f64) -> Result<(), Error> {
-        unsafe { set_rx_freq(self.usrp, freq); }
-        Ok(())
+        Ok((&self.ret_bufs, time_spec))
     }
 }
 
-#[allow(dead_code)]
-impl RadioRx for UsrpTxSingleStream {
-    /// Send the given samples through the transmit USRP
-    fn send(&mut self, data: &[Complex<f32>]) -> Result<(), Error> {
+impl RadioTx for UsrpTxSingleStream {
+    /// Send the given samples through the transmit USRP, scheduled and framed as given by `md`
+    fn send_burst(&mut self, data: &[Complex<f32>], md: &TxMetadata) -> Result<(), RadioError> {
         // Copy data into temporary buffer after making sure it is large enough
         self.buf.resize(2 * data.len(), 0.);
         for i in 0..data.len() {
@@ -226,25 +285,50 @@ impl RadioRx for UsrpTxSingleStream {
         }
 
         // Send the data
-        let err_code = unsafe { send(self.streamer.unwrap(), self.buf.as_mut_ptr(), data.len()) };
+        let err_code = unsafe {
+            send(
+                self.streamer.unwrap(),
+                self.buf.as_mut_ptr(),
+                data.len(),
+                md.time.is_some(),
+                md.time.unwrap_or(0.),
+                md.start_of_burst,
+                md.end_of_burst,
+            )
+        };
 
         // Interpret error code
         if err_code < 0 {
-            return Err(format_err!(
-                "Error in transmission. Error code: {}",
-                err_code
-            ));
+            return Err(tx_error(err_code));
         }
         return Ok(());
     }
 
     /// Set the center frequency (in Hz)
-    fn set_freq(&mut self, freq: f64) -> Result<(), Error> {
+    fn set_freq(&mut self, freq: f64) -> Result<(), RadioError> {
         unsafe { set_tx_freq(self.usrp, freq); };
         Ok(())
     }
 }
 
+impl RadioTxMulti for UsrpTxSingleStream {
+    /// The streamer sends a single channel
+    fn num_channels(&self) -> usize {
+        1
+    }
+
+    fn send_burst_multi(
+        &mut self,
+        data: &[&[Complex<f32>]],
+        md: &TxMetadata,
+    ) -> Result<(), RadioError> {
+        match data {
+            [data] => self.send_burst(data, md),
+            _ => Err(RadioError::ChannelMismatch),
+        }
+    }
+}
+
 impl Drop for UsrpRxSingleStream {
     fn drop(&mut self) {
         unsafe {
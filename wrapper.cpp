@@ -1,3 +1,4 @@
+#include <algorithm>
 #include <complex>
 #include <iostream>
 #include <vector>
@@ -23,6 +24,8 @@ struct RxStream {
 // A transmit stream
 struct TxStream {
   uhd::tx_streamer::sptr streamer;
+  // To know how long to wait for timed transmissions
+  uhd::usrp::multi_usrp::sptr usrp;
   std::complex<float>* buf;
   size_t buf_len;
 };
@@ -166,6 +169,11 @@ double get_rx_gain(MultiUsrp* usrp, size_t channel) {
   return usrp->usrp->get_rx_gain(channel);
 }
 
+// Get the number of receive channels. `recv` returns samples from all of them
+size_t get_rx_num_channels(MultiUsrp* usrp) {
+  return usrp->usrp->get_rx_num_channels();
+}
+
 void set_tx_freq(MultiUsrp* usrp, double freq) {
   uhd::tune_request_t tune_request(freq);
   usrp->usrp->set_tx_freq(tune_request, 0);
@@ -176,6 +184,15 @@ void set_rx_freq(MultiUsrp* usrp, double freq) {
   usrp->usrp->set_rx_freq(tune_request, 0);
 }
 
+// Read the "lo_locked" sensor of the given receive channel. Returns 1 if the LO
+// is locked, 0 if it isn't and -1 if the device has no such sensor
+int32_t get_rx_lo_locked(MultiUsrp* usrp, size_t chan) {
+  vector<string> sensors = usrp->usrp->get_rx_sensor_names(chan);
+  if (find(sensors.begin(), sensors.end(), "lo_locked") == sensors.end())
+    return -1;
+  return usrp->usrp->get_rx_sensor("lo_locked", chan).to_bool() ? 1 : 0;
+}
+
 // Get an Rx streamer from the USRP
 RxStream* get_rx_streamer(MultiUsrp* usrp) {
   // Make the channels (in some arbitrary order)
@@ -219,6 +236,7 @@ TxStream* get_tx_streamer(MultiUsrp* usrp) {
 
   TxStream* res = new TxStream;
   res->streamer = tx_stream;
+  res->usrp = usrp->usrp;
   res->buf = nullptr;
   res->buf_len = 0;
   return res;
@@ -329,10 +347,13 @@ int64_t recv(RxStream* streamer, float* buf, size_t num_samples,
 }
 
 // Send `num_samples` in `buf` to the Tx usrp. Elements (2*i, 2*i+1) are the
-// real and imaginary parts of the i^th sample respectively. Currently only a
-// single channel transmission is supported. Return value is negative in case of
-// an error
-int32_t send(TxStream* streamer, float* buf, size_t num_samples) {
+// real and imaginary parts of the i^th sample respectively. If `has_time_spec`
+// is true, transmission starts when the device time reaches `time` (in
+// seconds). `start_of_burst` and `end_of_burst` mark the first and last samples
+// as the start and end of a burst. Currently only a single channel
+// transmission is supported. Return value is negative in case of an error
+int32_t send(TxStream* streamer, float* buf, size_t num_samples,
+  bool has_time_spec, double time, bool start_of_burst, bool end_of_burst) {
   // Check that there is only a single channel
   if (streamer->streamer->get_num_channels() != 1)
     return -2;
@@ -349,8 +370,18 @@ int32_t send(TxStream* streamer, float* buf, size_t num_samples) {
   for (size_t i = 0; i < num_samples; ++i)
     streamer->buf[i] = complex<float>(buf[2*i], buf[2*i + 1]);
 
-  // Dummy metadata
   uhd::tx_metadata_t md;
+  md.start_of_burst = start_of_burst;
+  md.end_of_burst = end_of_burst;
+  md.has_time_spec = has_time_spec;
+  double timeout = 0.1;
+  if (has_time_spec) {
+    md.time_spec = uhd::time_spec_t(time);
+    // The device won't accept the samples until it is almost time to send them
+    double wait = time - streamer->usrp->get_time_now().get_real_secs();
+    if (wait > 0)
+      timeout += wait;
+  }
   // Dummy set of buffers
   vector<complex<float>*> buffs;
   buffs.push_back(streamer->buf);
@@ -358,7 +389,7 @@ int32_t send(TxStream* streamer, float* buf, size_t num_samples) {
   // Send the data
   size_t num_sent = 0;
   while (num_sent < num_samples) {
-    size_t num_new_sent = streamer->streamer->send(buffs, num_samples, md, 0.1);
+    size_t num_new_sent = streamer->streamer->send(buffs, num_samples - num_sent, md, timeout);
     num_sent += num_new_sent;
     buffs[0] += num_new_sent;
 
@@ -366,6 +397,11 @@ int32_t send(TxStream* streamer, float* buf, size_t num_samples) {
       // Probably timed out before we could send any packets
       return -3;
     }
+
+    // The remaining samples continue the burst that has already started
+    md.start_of_burst = false;
+    md.has_time_spec = false;
+    timeout = 0.1;
   }
 
   return 0;
@@ -14,14 +14,17 @@ MultiUsrp* new_usrp(char* args, double rate, double freq, double gain,
 int32_t set_clock_source(MultiUsrp* usrp, uint8_t source, size_t mboard);
 void set_rx_gain(MultiUsrp* usrp, double gain);
 double get_rx_gain(MultiUsrp* usrp, size_t chan);
+size_t get_rx_num_channels(MultiUsrp* usrp);
 void set_tx_freq(MultiUsrp* usrp, double freq);
 void set_rx_freq(MultiUsrp* usrp, double freq);
+int32_t get_rx_lo_locked(MultiUsrp* usrp, size_t chan);
 void set_time_now(MultiUsrp* usrp, double now);
 RxStream* get_rx_streamer(MultiUsrp* usrp);
 TxStream* get_tx_streamer(MultiUsrp* usrp);
 int64_t recv(RxStream* streamer, float* buf, size_t num_samples,
   size_t num_channels);
-int32_t send(TxStream* streamer, float* buf, size_t num_samples);
+int32_t send(TxStream* streamer, float* buf, size_t num_samples,
+  bool has_time_spec, double time, bool start_of_burst, bool end_of_burst);
 void delete_usrp(MultiUsrp* usrp);
 void delete_rx_stream(RxStream* streamer);
 void delete_tx_stream(TxStream* streamer);